@@ -0,0 +1,340 @@
+use lazy_static::lazy_static;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    start_idle_timer, CancellationToken, ClientSigningKey, EnigmatickError, EnigmatickState,
+    IdleTimer, SignatureFormat, StateSecrets, DEFAULT_KDF_ITERATIONS, DEFAULT_KDF_MEMORY_KIB,
+};
+
+// Signed-in accounts, each with its own state, secrets and server settings
+
+lazy_static! {
+    // accounts that have a profile, by actor ID
+    static ref ACCOUNTS: Mutex<HashMap<String, Arc<Account>>> = Mutex::new(HashMap::new());
+
+    // starts out without a profile and is added to ACCOUNTS once it has one
+    static ref ACTIVE_ACCOUNT: Mutex<Arc<Account>> = Mutex::new(Arc::new(Account::new()));
+}
+
+thread_local! {
+    static SCOPED_ACCOUNT: RefCell<Option<Arc<Account>>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug)]
+pub struct Account {
+    pub(crate) state: Mutex<EnigmatickState>,
+    pub(crate) secrets: Mutex<StateSecrets>,
+    // the client key parsed from the secrets, with the hash of its PEM; see
+    // client_signing_key
+    pub(crate) signing_key: Mutex<Option<(Vec<u8>, Arc<ClientSigningKey>)>>,
+    // set by lock(); see lock.rs
+    pub(crate) locked: AtomicBool,
+    pub(crate) idle: IdleTimer,
+    // milliseconds to add to local_now() to get the server's time; see clock.rs
+    pub(crate) clock_offset: AtomicI64,
+    // the signature format to use per host; hosts not listed get Cavage
+    pub(crate) signature_formats: Mutex<HashMap<String, SignatureFormat>>,
+    // the Argon2i cost for new keys; see set_kdf_params
+    pub(crate) kdf_iterations: AtomicU32,
+    pub(crate) kdf_memory_kib: AtomicU32,
+    // set while the outbox queue is being drained
+    pub(crate) draining: AtomicBool,
+    // where storage.rs keeps its files in place of storage_home()
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) storage_dir: Mutex<Option<std::path::PathBuf>>,
+    // owned by a Client rather than kept in ACCOUNTS
+    detached: bool,
+    // requests and background work for the account; cancelled at logout
+    session: Mutex<CancellationToken>,
+}
+
+impl Default for Account {
+    fn default() -> Self {
+        Account::new()
+    }
+}
+
+impl Account {
+    pub fn new() -> Self {
+        Account {
+            state: Mutex::new(EnigmatickState::new()),
+            secrets: Mutex::new(StateSecrets::default()),
+            signing_key: Mutex::new(None),
+            locked: AtomicBool::new(false),
+            idle: IdleTimer::default(),
+            clock_offset: AtomicI64::new(0),
+            signature_formats: Mutex::new(HashMap::new()),
+            kdf_iterations: AtomicU32::new(DEFAULT_KDF_ITERATIONS),
+            kdf_memory_kib: AtomicU32::new(DEFAULT_KDF_MEMORY_KIB),
+            draining: AtomicBool::new(false),
+            #[cfg(not(target_arch = "wasm32"))]
+            storage_dir: Mutex::new(None),
+            detached: false,
+            session: Mutex::new(CancellationToken::new()),
+        }
+    }
+
+    // An account that is never added to ACCOUNTS, so that it is dropped (and
+    // its secrets wiped) along with its owner
+    pub fn detached() -> Self {
+        Account {
+            detached: true,
+            ..Account::new()
+        }
+    }
+
+    // The actor ID, once the account has a profile
+    pub fn id(&self) -> Option<String> {
+        self.state
+            .lock()
+            .ok()?
+            .profile
+            .as_ref()
+            .map(|x| x.id.to_string())
+    }
+
+    // As id, but None rather than waiting if the state is locked
+    pub(crate) fn try_id(&self) -> Option<String> {
+        self.state
+            .try_lock()
+            .ok()?
+            .profile
+            .as_ref()
+            .map(|x| x.id.to_string())
+    }
+
+    pub fn session(&self) -> CancellationToken {
+        self.session.lock().map(|x| x.clone()).unwrap_or_default()
+    }
+
+    // Abandons everything in flight for the account; anything started
+    // afterward gets a new token
+    pub(crate) fn end_session(&self) {
+        if let Ok(mut token) = self.session.lock() {
+            std::mem::take(&mut *token).cancel();
+        }
+    }
+
+    // The parsed client key goes whenever the secrets it came from do
+    pub(crate) fn forget_signing_key(&self) {
+        if let Ok(mut signing_key) = self.signing_key.lock() {
+            *signing_key = None;
+        }
+    }
+
+    // Drops the profile and secrets (the old secrets are wiped as they are
+    // dropped), keeping only the server
+    pub(crate) fn clear(&self) {
+        if let Ok(mut secrets) = self.secrets.lock() {
+            *secrets = StateSecrets::default();
+        }
+        self.forget_signing_key();
+
+        if let Ok(mut state) = self.state.lock() {
+            let server_name = state.server_name.take();
+            let server_url = state.server_url.take();
+
+            *state = EnigmatickState::new();
+            state.server_name = server_name;
+            state.server_url = server_url;
+        }
+
+        self.locked.store(false, Ordering::SeqCst);
+    }
+}
+
+pub fn current_account() -> Arc<Account> {
+    if let Some(account) = SCOPED_ACCOUNT.with(|x| x.borrow().clone()) {
+        return account;
+    }
+
+    ACTIVE_ACCOUNT
+        .lock()
+        .map(|x| x.clone())
+        .unwrap_or_else(|_| Arc::new(Account::new()))
+}
+
+pub fn get_account(actor_id: &str) -> Option<Arc<Account>> {
+    ACCOUNTS.lock().ok()?.get(actor_id).cloned()
+}
+
+pub(crate) fn unregister_account(account: &Arc<Account>) {
+    if let Ok(mut accounts) = ACCOUNTS.lock() {
+        accounts.retain(|_, x| !Arc::ptr_eq(x, account));
+    }
+}
+
+// Called after the state of an account changes; an account is added (or
+// replaces an earlier sign-in of the same actor) when it first has a profile
+pub(crate) fn register_account(account: &Arc<Account>) {
+    if account.detached {
+        return;
+    }
+
+    let Some(id) = account.id() else {
+        return;
+    };
+
+    if let Ok(mut accounts) = ACCOUNTS.lock() {
+        if !accounts.get(&id).is_some_and(|x| Arc::ptr_eq(x, account)) {
+            accounts.insert(id, account.clone());
+        }
+    }
+}
+
+// Makes sure that signing in does not replace an account that is signed in.
+// If the current account has a profile, a new account is made active with the
+// same server, and the enclosing with_account (e.g., the exports' default
+// Client) moves onto it; otherwise, or if the current account belongs to a
+// Client of its own (see Account::detached), it is used as it is.
+pub fn begin_account() -> Arc<Account> {
+    let current = current_account();
+
+    if current.id().is_none() || current.detached {
+        return current;
+    }
+
+    let account = Arc::new(Account::new());
+    if let (Ok(from), Ok(mut to)) = (current.state.lock(), account.state.lock()) {
+        to.server_name = from.server_name.clone();
+        to.server_url = from.server_url.clone();
+    }
+
+    // the server is the same, and so are the settings made for it
+    account.clock_offset.store(
+        current.clock_offset.load(Ordering::SeqCst),
+        Ordering::SeqCst,
+    );
+    if let (Ok(from), Ok(mut to)) = (
+        current.signature_formats.lock(),
+        account.signature_formats.lock(),
+    ) {
+        *to = from.clone();
+    }
+    account.kdf_iterations.store(
+        current.kdf_iterations.load(Ordering::SeqCst),
+        Ordering::SeqCst,
+    );
+    account.kdf_memory_kib.store(
+        current.kdf_memory_kib.load(Ordering::SeqCst),
+        Ordering::SeqCst,
+    );
+    #[cfg(not(target_arch = "wasm32"))]
+    if let (Ok(from), Ok(mut to)) = (current.storage_dir.lock(), account.storage_dir.lock()) {
+        *to = from.clone();
+    }
+    start_idle_timer(&account, current.idle.timeout_ms());
+
+    activate_account(account.clone());
+    SCOPED_ACCOUNT.with(|x| {
+        if let Some(scoped) = x.borrow_mut().as_mut() {
+            *scoped = account.clone();
+        }
+    });
+
+    account
+}
+
+// Runs f against account instead of the active one
+pub fn with_account<F: Future>(account: Arc<Account>, f: F) -> impl Future<Output = F::Output> {
+    WithAccount {
+        account,
+        inner: Box::pin(f),
+    }
+}
+
+struct WithAccount<F: Future> {
+    account: Arc<Account>,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for WithAccount<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let previous = SCOPED_ACCOUNT.with(|x| x.replace(Some(self.account.clone())));
+        let result = self.inner.as_mut().poll(cx);
+
+        // begin_account may have moved the scope onto a new account
+        if let Some(account) = SCOPED_ACCOUNT.with(|x| x.replace(previous)) {
+            self.account = account;
+        }
+
+        result
+    }
+}
+
+// The synchronous counterpart of with_account
+pub fn in_account<T>(account: Arc<Account>, f: impl FnOnce() -> T) -> T {
+    let previous = SCOPED_ACCOUNT.with(|x| x.replace(Some(account)));
+    let result = f();
+    SCOPED_ACCOUNT.with(|x| *x.borrow_mut() = previous);
+
+    result
+}
+
+// Actor IDs of the signed-in accounts
+#[wasm_bindgen]
+pub fn list_accounts() -> Vec<String> {
+    let mut ids: Vec<String> = ACCOUNTS
+        .lock()
+        .map(|x| x.keys().cloned().collect())
+        .unwrap_or_default();
+    ids.sort();
+    ids
+}
+
+#[wasm_bindgen]
+pub fn active_account() -> Option<String> {
+    current_account().id()
+}
+
+pub(crate) fn activate_account(account: Arc<Account>) {
+    if let Ok(mut active) = ACTIVE_ACCOUNT.lock() {
+        *active = account;
+    }
+}
+
+#[wasm_bindgen]
+pub fn switch_account(actor_id: String) -> Result<(), EnigmatickError> {
+    let account =
+        get_account(&actor_id).ok_or(EnigmatickError::missing(&format!("account {actor_id}")))?;
+
+    activate_account(account);
+    Ok(())
+}
+
+// Starts a new account (e.g., before load_instance_information for another
+// server) without signing out of the active one
+#[wasm_bindgen]
+pub fn add_account() {
+    begin_account();
+}
+
+// Drops an account and its secrets, abandoning whatever is in flight for it
+// (the server session is left alone; see logout); if it was active, a new
+// account without a profile takes its place
+#[wasm_bindgen]
+pub fn remove_account(actor_id: String) {
+    let Some(removed) = ACCOUNTS.lock().ok().and_then(|mut x| x.remove(&actor_id)) else {
+        return;
+    };
+
+    if let Ok(mut active) = ACTIVE_ACCOUNT.lock() {
+        if Arc::ptr_eq(&removed, &active) {
+            *active = Arc::new(Account::new());
+        }
+    }
+
+    // a Client or task may still hold the account, so its secrets are wiped
+    // here rather than when it is dropped
+    removed.end_session();
+    removed.clear();
+}
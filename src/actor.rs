@@ -1,23 +1,35 @@
-use crate::{authenticated, log, EnigmatickState, Profile};
+use crate::{authenticated, log, EnigmatickError, EnigmatickResult, EnigmatickState, Profile};
 use jdt_activity_pub::{ApActor, ApCollection};
 use js_sys::Promise;
-use wasm_bindgen::prelude::wasm_bindgen;
 use wasm_bindgen_futures::future_to_promise;
 
-use crate::{get_state, send_get, send_get_promise, HANDLE_RE, URL_RE};
+use crate::{
+    get_state, send_get, send_get_promise, with_current_client, with_request_options,
+    CancellationToken, RequestOptions,
+    HANDLE_RE, URL_RE,
+};
 
 #[cfg(target_arch = "wasm32")]
 use crate::EnigmatickCache;
 
-#[wasm_bindgen]
 pub async fn get_remote_resource(
     resource: String,
     webfinger: String,
     page: Option<String>,
-) -> Option<String> {
+) -> Result<String, EnigmatickError> {
     let state = get_state();
-    let profile = format!("user/{}/", state.profile.clone()?.username);
-    let server_name = state.server_name.clone()?;
+    let profile = format!(
+        "user/{}/",
+        state
+            .profile
+            .clone()
+            .ok_or(EnigmatickError::NotAuthenticated)?
+            .username
+    );
+    let server_name = state
+        .server_name
+        .clone()
+        .ok_or(EnigmatickError::missing("server_name"))?;
 
     let url = match page {
         Some(page) => format!(
@@ -30,135 +42,169 @@ pub async fn get_remote_resource(
     send_get(Some(server_name), url, "application/json".to_string()).await
 }
 
-pub async fn get_remote_keys(webfinger: String) -> Option<ApCollection> {
+pub async fn get_remote_keys(webfinger: String) -> EnigmatickResult<ApCollection> {
     let state = get_state();
-    let profile = format!("user/{}/", state.profile.clone()?.username);
-    let server_name = state.server_name.clone()?;
+    let profile = format!(
+        "user/{}/",
+        state
+            .profile
+            .clone()
+            .ok_or(EnigmatickError::NotAuthenticated)?
+            .username
+    );
+    let server_name = state
+        .server_name
+        .clone()
+        .ok_or(EnigmatickError::missing("server_name"))?;
 
     let url = format!("/api/{profile}remote/keys?webfinger={webfinger}");
 
-    send_get(Some(server_name), url, "application/json".to_string())
-        .await
-        .and_then(|x| serde_json::from_str(&x).ok())
+    let keys = send_get(Some(server_name), url, "application/json".to_string()).await?;
+
+    Ok(serde_json::from_str(&keys)?)
 }
 
-#[wasm_bindgen]
-pub async fn get_remote_following(webfinger: String, page: Option<String>) -> Option<String> {
+pub async fn get_remote_following(
+    webfinger: String,
+    page: Option<String>,
+) -> Result<String, EnigmatickError> {
     get_remote_resource("following".to_string(), webfinger, page).await
 }
 
-#[wasm_bindgen]
-pub async fn get_remote_followers(webfinger: String, page: Option<String>) -> Option<String> {
+pub async fn get_remote_followers(
+    webfinger: String,
+    page: Option<String>,
+) -> Result<String, EnigmatickError> {
     get_remote_resource("followers".to_string(), webfinger, page).await
 }
 
-#[wasm_bindgen]
-pub async fn get_remote_outbox(webfinger: String, page: Option<String>) -> Option<String> {
+pub async fn get_remote_outbox(
+    webfinger: String,
+    page: Option<String>,
+) -> Result<String, EnigmatickError> {
     get_remote_resource("outbox".to_string(), webfinger, page).await
 }
 
-#[wasm_bindgen]
-pub fn get_actor_from_webfinger_promise(webfinger: String) -> Promise {
+// timeout_ms and cancellation apply to this lookup only (see cancel.rs)
+pub fn get_actor_from_webfinger_promise(
+    webfinger: String,
+    timeout_ms: Option<u32>,
+    cancellation: Option<CancellationToken>,
+) -> Promise {
     let state = get_state();
     let authenticated = state.is_authenticated();
 
-    let url = {
-        if authenticated {
-            let username = state.profile.clone().unwrap().username;
+    let url = match state.profile.clone() {
+        Some(profile) if authenticated => {
+            let username = profile.username;
             format!("/api/user/{username}/remote/actor?webfinger={webfinger}")
-        } else {
-            format!("/api/remote/actor?webfinger={webfinger}")
         }
+        _ => format!("/api/remote/actor?webfinger={webfinger}"),
     };
 
-    future_to_promise(send_get_promise(None, url, "application/json".to_string()))
+    // the promise is polled outside of any Client, so it is tied to this one
+    future_to_promise(with_current_client(with_request_options(
+        RequestOptions::of(timeout_ms, cancellation),
+        send_get_promise(None, url, "application/json".to_string()),
+    )))
 }
 
 #[cfg(target_arch = "wasm32")]
-#[wasm_bindgen]
 pub async fn get_actor_cached(cache: &EnigmatickCache, id: String) -> Option<Promise> {
     if let Some(promise) = cache.get(&id.clone()) {
         //log(&format!("SHORT CIRCUITING GET_ACTOR_CACHED: {id}"));
         return Some(promise);
     }
 
+    // get_actor_from_webfinger_promise fetches through the user's endpoint
+    // when authenticated; those entries are dropped at logout
+    let state = get_state();
+    let owner = state
+        .profile
+        .filter(|_| state.authenticated)
+        .map(|x| x.id.to_string());
+
     if URL_RE.is_match(&id) {
         //log(&format!("GETTING ID: {id}"));
-        let webfinger = get_webfinger_from_id(id.clone()).await?;
+        let webfinger = get_webfinger_from_id(id.clone()).await.ok()?;
 
-        let p = get_actor_from_webfinger_promise(webfinger);
-        cache.set(&id, p.clone());
+        let p = get_actor_from_webfinger_promise(webfinger, None, None);
+        cache.set_for(&id, p.clone(), owner.clone());
     } else if HANDLE_RE.is_match(&id) {
         //log(&format!("GETTING WEBFINGER: {id}"));
 
-        let p = get_actor_from_webfinger_promise(id.clone());
-        cache.set(&id, p.clone());
+        let p = get_actor_from_webfinger_promise(id.clone(), None, None);
+        cache.set_for(&id, p.clone(), owner.clone());
     }
 
     cache.get(&id.clone())
 }
 
-#[wasm_bindgen]
-pub async fn get_actor(id: String) -> Option<String> {
-    if URL_RE.is_match(&id) {
-        let webfinger = get_webfinger_from_id(id).await?;
-
-        get_actor_from_webfinger(webfinger)
-            .await
-            .and_then(|x| serde_json::to_string(&x).ok())
+pub async fn get_actor_with_options(
+    id: String,
+    options: RequestOptions,
+) -> EnigmatickResult<String> {
+    let webfinger = if URL_RE.is_match(&id) {
+        with_request_options(options.clone(), get_webfinger_from_id(id)).await?
     } else if HANDLE_RE.is_match(&id) {
-        get_actor_from_webfinger(id)
-            .await
-            .and_then(|x| serde_json::to_string(&x).ok())
+        id
     } else {
-        None
-    }
+        return Err(EnigmatickError::InvalidInput(format!(
+            "{id} is neither an actor URL nor a handle"
+        )));
+    };
+
+    Ok(serde_json::to_string(
+        &get_actor_from_webfinger(webfinger, options).await?,
+    )?)
 }
 
-pub async fn get_actor_from_webfinger(webfinger: String) -> Option<ApActor> {
+pub async fn get_actor_from_webfinger(
+    webfinger: String,
+    options: RequestOptions,
+) -> EnigmatickResult<ApActor> {
     let state = get_state();
     let authenticated = state.is_authenticated();
 
-    let url = {
-        if authenticated {
-            let username = state.profile.clone()?.username;
+    let url = match state.profile.clone() {
+        Some(profile) if authenticated => {
+            let username = profile.username;
             format!("/api/user/{username}/remote/actor?webfinger={webfinger}")
-        } else {
-            format!("/api/remote/actor?webfinger={webfinger}")
         }
+        _ => format!("/api/remote/actor?webfinger={webfinger}"),
     };
 
-    send_get(None, url, "application/json".to_string())
-        .await
-        .and_then(|x| serde_json::from_str(&x).ok())
+    let actor =
+        with_request_options(options, send_get(None, url, "application/json".to_string())).await?;
+
+    Ok(serde_json::from_str(&actor)?)
 }
 
-#[wasm_bindgen]
-pub async fn get_webfinger_from_id(id: String) -> Option<String> {
+pub async fn get_webfinger_from_id(id: String) -> Result<String, EnigmatickError> {
     let id = urlencoding::encode(&id);
 
     let state = get_state();
     let authenticated = state.is_authenticated();
 
-    let url = {
-        if authenticated {
-            let username = state.profile.clone()?.username;
+    let url = match state.profile.clone() {
+        Some(profile) if authenticated => {
+            let username = profile.username;
             format!("/api/user/{username}/remote/webfinger?id={id}")
-        } else {
-            format!("/api/remote/webfinger?id={id}")
         }
+        _ => format!("/api/remote/webfinger?id={id}"),
     };
 
     send_get(None, url, "application/json".to_string()).await
 }
 
-#[wasm_bindgen]
-pub async fn get_webfinger_from_handle(handle: String) -> Option<String> {
+pub async fn get_webfinger_from_handle(handle: String) -> Result<String, EnigmatickError> {
     authenticated(
         move |state: EnigmatickState, _profile: Profile| async move {
-            let server = state.get_server_name();
+            let server = state
+                .get_server_name()
+                .ok_or(EnigmatickError::missing("server_name"))?;
 
-            server.map(|server| format!("@{handle}@{server}"))
+            Ok(format!("@{handle}@{server}"))
         },
     )
     .await
@@ -0,0 +1,142 @@
+use base64::{engine::general_purpose, engine::Engine as _};
+use orion::aead::SecretKey;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+use crate::{
+    authenticated, get_hash, resolve_url, send_request, stream_nonce, upload_file, EncryptWriter,
+    EnigmatickError, EnigmatickResult, EnigmatickState, HttpRequest, Method, Profile,
+    StreamDecryptor,
+};
+
+// Attachments to encrypted notes, sealed under a per-file key sent in the MLS message
+
+const ENCRYPTED_MESSAGE_TYPE: &str = "EncryptedMessage";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EncryptedAttachment {
+    pub url: String,
+    pub media_type: String,
+    pub name: Option<String>,
+    // base64; the key and the stream nonce from the ciphertext header
+    pub key: String,
+    pub nonce: String,
+    // get_hash of the plaintext
+    pub hash: String,
+    pub size: u64,
+}
+
+// The plaintext of an MLS application message. Messages without attachments
+// are sent as the bare content, as they always have been.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EncryptedMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    pub content: String,
+    #[serde(default)]
+    pub attachments: Vec<EncryptedAttachment>,
+}
+
+impl EncryptedMessage {
+    pub fn new(content: String, attachments: Vec<EncryptedAttachment>) -> Self {
+        EncryptedMessage {
+            kind: ENCRYPTED_MESSAGE_TYPE.to_string(),
+            content,
+            attachments,
+        }
+    }
+
+    pub fn parse(message: &str) -> Self {
+        serde_json::from_str::<EncryptedMessage>(message)
+            .ok()
+            .filter(|x| x.kind == ENCRYPTED_MESSAGE_TYPE)
+            .unwrap_or_else(|| EncryptedMessage::new(message.to_string(), vec![]))
+    }
+
+    pub fn encode(&self) -> EnigmatickResult<String> {
+        if self.attachments.is_empty() {
+            Ok(self.content.clone())
+        } else {
+            Ok(serde_json::to_string(self)?)
+        }
+    }
+}
+
+// Encrypts and uploads a file for use in an encrypted note; the returned JSON
+// is passed to NoteParams::add_encrypted_attachment
+pub async fn upload_encrypted_attachment(
+    data: &[u8],
+    media_type: String,
+    name: Option<String>,
+) -> Result<String, EnigmatickError> {
+    let key = general_purpose::STANDARD.encode(SecretKey::default().unprotected_as_bytes());
+
+    let mut writer = EncryptWriter::new(vec![], Some(key.clone()))?;
+    writer.write_all(data)?;
+    let sealed = writer.finish()?;
+
+    let nonce = stream_nonce(&sealed)
+        .map(|x| general_purpose::STANDARD.encode(x))
+        .ok_or(EnigmatickError::missing("stream nonce"))?;
+
+    authenticated(move |_: EnigmatickState, profile: Profile| async move {
+        let upload = format!("/api/user/{}/media/encrypted", profile.username);
+
+        let response = upload_file(None, upload, &sealed).await?;
+        let uploaded: serde_json::Value = serde_json::from_str(&response)?;
+        let url = uploaded
+            .get("url")
+            .and_then(|x| x.as_str())
+            .ok_or(EnigmatickError::parse("upload response has no url"))?;
+
+        Ok(serde_json::to_string(&EncryptedAttachment {
+            url: url.to_string(),
+            media_type,
+            name,
+            key,
+            nonce,
+            hash: get_hash(data.to_vec())?,
+            size: data.len() as u64,
+        })?)
+    })
+    .await
+}
+
+// Splits a decrypted message (e.g., vault content) into its content and
+// attachments; returns EncryptedMessage JSON
+pub fn open_encrypted_message(message: String) -> Result<String, EnigmatickError> {
+    Ok(serde_json::to_string(&EncryptedMessage::parse(&message))?)
+}
+
+// Downloads and decrypts an attachment from an EncryptedMessage, checking
+// that it is the file the sender described
+pub async fn decrypt_attachment(attachment: String) -> Result<Vec<u8>, EnigmatickError> {
+    let attachment: EncryptedAttachment = serde_json::from_str(&attachment)?;
+
+    let sealed = send_request(HttpRequest::new(Method::Get, resolve_url(&attachment.url)))
+        .await?
+        .error_for_status()?
+        .body;
+
+    let mismatch = |x: &str| EnigmatickError::Verification(format!("attachment {x} mismatch"));
+
+    if stream_nonce(&sealed).map(|x| general_purpose::STANDARD.encode(x))
+        != Some(attachment.nonce.clone())
+    {
+        return Err(mismatch("nonce"));
+    }
+
+    let mut decryptor = StreamDecryptor::new(Some(attachment.key))?;
+    let mut data = decryptor.push(&sealed)?;
+    data.extend(decryptor.finish()?);
+
+    if data.len() as u64 != attachment.size {
+        return Err(mismatch("size"));
+    }
+
+    if get_hash(data.clone())? != attachment.hash {
+        return Err(mismatch("hash"));
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,232 @@
+use base64::{engine::general_purpose, engine::Engine as _};
+use chrono::Utc;
+use jdt_activity_pub::{ActivityPub, ApCollection, ApInstrument, ApObject, Collectible};
+use orion::{aead, kdf};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    authenticated, client_key_id, client_signing_key, decrypt_bytes, encrypt_bytes, get_mls_keys,
+    get_vault_items, publish_client_key, store_vault_items, update_instruments, update_state,
+    with_secrets, ClientKeyPem, EnigmatickError, EnigmatickResult, EnigmatickState, KdfParams,
+    Profile, SecretString, DEFAULT_KEY_GRACE_PERIOD,
+};
+
+// A passphrase-protected JSON envelope around a sealed BackupContents
+
+const BACKUP_FORMAT: &str = "enigmatick-backup";
+const BACKUP_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct BackupEnvelope {
+    format: String,
+    version: u32,
+    created_at: String,
+    kdf: KdfParams,
+    salt: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct BackupContents {
+    actor: String,
+    client_private_key_pem: SecretString,
+    client_key_id: Option<String>,
+    olm_pickled_account: Option<SecretString>,
+    // the MLS credentials and storage instruments
+    mls: Vec<ApInstrument>,
+    vault: Vec<ApInstrument>,
+}
+
+fn backup_key(
+    passphrase: &str,
+    salt: &kdf::Salt,
+    params: &KdfParams,
+) -> EnigmatickResult<kdf::SecretKey> {
+    let password =
+        kdf::Password::from_slice(passphrase.as_bytes()).map_err(EnigmatickError::encryption)?;
+
+    kdf::derive_key(&password, salt, params.iterations, params.memory_kib, 32)
+        .map_err(EnigmatickError::encryption)
+}
+
+// Instrument content is sealed under the account's derived key; in a backup it
+// is held as base64 plaintext instead so that it can be sealed again under
+// whatever key the restoring account has
+pub(crate) fn open_instrument(mut instrument: ApInstrument) -> EnigmatickResult<ApInstrument> {
+    let content = instrument
+        .content
+        .clone()
+        .ok_or(EnigmatickError::missing("instrument content"))?;
+
+    instrument.content = Some(general_purpose::STANDARD.encode(decrypt_bytes(None, content)?));
+    Ok(instrument)
+}
+
+pub(crate) fn seal_instrument(mut instrument: ApInstrument) -> EnigmatickResult<ApInstrument> {
+    let content = instrument
+        .content
+        .clone()
+        .ok_or(EnigmatickError::missing("instrument content"))?;
+    let plaintext = general_purpose::STANDARD.decode(content)?;
+
+    instrument.content = Some(general_purpose::STANDARD.encode(encrypt_bytes(None, &plaintext)?));
+    Ok(instrument)
+}
+
+pub(crate) fn instruments(collection: ApCollection) -> Vec<ApInstrument> {
+    collection
+        .items()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|item| match item {
+            ActivityPub::Object(ApObject::Instrument(x)) => Some(x),
+            _ => None,
+        })
+        .collect()
+}
+
+// Returns a passphrase-protected backup of everything needed to read this
+// account's encrypted history: the client key, olm account, MLS credentials
+// and storage, and the vault
+pub async fn export_backup(passphrase: String) -> Result<String, EnigmatickError> {
+    authenticated(move |state: EnigmatickState, profile: Profile| async move {
+        let mls = instruments(get_mls_keys().await?)
+            .into_iter()
+            .filter(|x| x.is_mls_credentials() || x.is_mls_storage())
+            .map(open_instrument)
+            .collect::<EnigmatickResult<Vec<ApInstrument>>>()?;
+        let vault = get_vault_items(&profile)
+            .await?
+            .into_iter()
+            .map(open_instrument)
+            .collect::<EnigmatickResult<Vec<ApInstrument>>>()?;
+
+        let key_id = client_key_id(&state, &profile)?;
+
+        let params = KdfParams::current();
+        let salt = kdf::Salt::default();
+        let key = backup_key(&passphrase, &salt, &params)?;
+
+        // the secrets are serialized and sealed without leaving the lock
+        let ciphertext = with_secrets(|secrets| {
+            let contents = Zeroizing::new(serde_json::to_string(&BackupContents {
+                actor: profile.id.to_string(),
+                client_private_key_pem: secrets.client_private_key_pem()?.clone(),
+                client_key_id: Some(key_id),
+                olm_pickled_account: secrets.olm_pickled_account().ok().cloned(),
+                mls,
+                vault,
+            })?);
+
+            aead::seal(&key, contents.as_bytes()).map_err(EnigmatickError::encryption)
+        })?;
+
+        Ok(serde_json::to_string(&BackupEnvelope {
+            format: BACKUP_FORMAT.to_string(),
+            version: BACKUP_VERSION,
+            created_at: Utc::now().to_rfc3339(),
+            kdf: params,
+            salt: general_purpose::STANDARD.encode(&salt),
+            ciphertext: general_purpose::STANDARD.encode(ciphertext),
+        })?)
+    })
+    .await
+}
+
+fn open_backup(backup: &str, passphrase: &str) -> EnigmatickResult<BackupContents> {
+    let envelope: BackupEnvelope = serde_json::from_str(backup)?;
+
+    if envelope.format != BACKUP_FORMAT {
+        return Err(EnigmatickError::InvalidInput(
+            "not an Enigmatick backup".to_string(),
+        ));
+    }
+
+    if envelope.version > BACKUP_VERSION {
+        return Err(EnigmatickError::InvalidInput(format!(
+            "backup version {} is newer than this client supports",
+            envelope.version
+        )));
+    }
+
+    let salt = kdf::Salt::from_slice(&general_purpose::STANDARD.decode(&envelope.salt)?)
+        .map_err(EnigmatickError::decryption)?;
+    let key = backup_key(passphrase, &salt, &envelope.kdf)?;
+
+    // a wrong passphrase surfaces here as a failed authentication tag
+    let plaintext = Zeroizing::new(
+        aead::open(
+            &key,
+            &general_purpose::STANDARD.decode(&envelope.ciphertext)?,
+        )
+        .map_err(|_| {
+            EnigmatickError::Decryption("wrong passphrase or damaged backup".to_string())
+        })?,
+    );
+
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+// Restores a backup onto the logged-in account (e.g., right after logging in
+// on a fresh device or after creating an account on a new server). The backed
+// up client key replaces the current one; the MLS instruments and vault are
+// sealed under this account's key and stored. A backup made by another actor
+// (e.g., the same person on another server) is refused unless
+// allow_other_actor is set.
+pub async fn import_backup(
+    backup: String,
+    passphrase: String,
+    allow_other_actor: Option<bool>,
+) -> Result<(), EnigmatickError> {
+    let contents = open_backup(&backup, &passphrase)?;
+
+    authenticated(move |state: EnigmatickState, profile: Profile| async move {
+        if contents.actor != profile.id.to_string() && !allow_other_actor.unwrap_or(false) {
+            return Err(EnigmatickError::InvalidInput(format!(
+                "backup belongs to {}, not {}",
+                contents.actor, profile.id
+            )));
+        }
+
+        let private_key = contents.client_private_key_pem.expose().to_string();
+        let key = ClientKeyPem {
+            public_key: client_signing_key(&private_key)?.public_key_pem()?,
+            private_key,
+        };
+
+        publish_client_key(&state, &profile, key, DEFAULT_KEY_GRACE_PERIOD).await?;
+
+        // the olm account is only stored on the server along with a password
+        // change, so it is restored for this session
+        if let Some(olm_pickled_account) = contents.olm_pickled_account {
+            update_state(|state| {
+                state.set_olm_pickled_account(olm_pickled_account.expose().to_string());
+                Ok(())
+            })?;
+        }
+
+        let mls = contents
+            .mls
+            .into_iter()
+            .map(seal_instrument)
+            .collect::<EnigmatickResult<Vec<ApInstrument>>>()?;
+
+        if !mls.is_empty() {
+            update_instruments(mls).await?;
+        }
+
+        let vault = contents
+            .vault
+            .into_iter()
+            .map(seal_instrument)
+            .collect::<EnigmatickResult<Vec<ApInstrument>>>()?;
+
+        if !vault.is_empty() {
+            store_vault_items(&profile, vault).await?;
+        }
+
+        Ok(())
+    })
+    .await
+}
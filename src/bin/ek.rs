@@ -1,7 +1,11 @@
+use std::collections::HashMap;
 use std::process::exit;
 
-use enigmatick_wasm::{load_instance_information, InstanceInformation};
-use reedline_repl_rs::clap::{Arg, ArgMatches, Command};
+use enigmatick_wasm::{
+    authenticate, export_backup, import_backup, load_instance_information, verify_with_actor,
+    InstanceInformation, VerifyParams,
+};
+use reedline_repl_rs::clap::{Arg, ArgAction, ArgMatches, Command};
 use reedline_repl_rs::{Repl, Result};
 
 async fn hello<T>(args: ArgMatches, _context: &mut T) -> Result<Option<String>> {
@@ -16,8 +20,111 @@ async fn update_prompt<T>(_context: &mut T) -> Result<Option<String>> {
 }
 
 async fn server<T>(args: ArgMatches, _context: &mut T) -> Result<Option<String>> {
-    load_instance_information(Some(args.get_one::<String>("url").unwrap().to_string())).await;
-    Ok(Some("wtfever".to_string()))
+    let instance: Result<InstanceInformation, _> =
+        load_instance_information(Some(args.get_one::<String>("url").unwrap().to_string())).await;
+
+    Ok(Some(match instance {
+        Ok(instance) => format!("{} ({})", instance.title, instance.url),
+        Err(e) => format!("{} [{}]", e, e.code()),
+    }))
+}
+
+// Reads a password or passphrase from the terminal without echoing it, so
+// that it stays out of the history and off the screen
+fn prompt(name: &str) -> std::result::Result<String, String> {
+    rpassword::prompt_password(format!("{name}: "))
+        .map_err(|e| format!("unable to read {name}: {e}"))
+}
+
+async fn login<T>(args: ArgMatches, _context: &mut T) -> Result<Option<String>> {
+    let username = args.get_one::<String>("username").unwrap().to_string();
+    let password = match prompt("password") {
+        Ok(password) => password,
+        Err(e) => return Ok(Some(e)),
+    };
+
+    Ok(Some(match authenticate(username, password).await {
+        Ok(profile) => format!("logged in as {}", profile.username),
+        Err(e) => format!("{} [{}]", e, e.code()),
+    }))
+}
+
+async fn backup<T>(args: ArgMatches, _context: &mut T) -> Result<Option<String>> {
+    let file = args.get_one::<String>("file").unwrap();
+    let passphrase = match (prompt("passphrase"), prompt("passphrase (again)")) {
+        (Ok(passphrase), Ok(again)) if passphrase == again => passphrase,
+        (Ok(_), Ok(_)) => return Ok(Some("the passphrases do not match".to_string())),
+        (Err(e), _) | (_, Err(e)) => return Ok(Some(e)),
+    };
+
+    Ok(Some(match export_backup(passphrase).await {
+        Ok(backup) => match std::fs::write(file, backup) {
+            Ok(()) => format!("backup written to {file}"),
+            Err(e) => format!("unable to write {file}: {e}"),
+        },
+        Err(e) => format!("{} [{}]", e, e.code()),
+    }))
+}
+
+async fn restore<T>(args: ArgMatches, _context: &mut T) -> Result<Option<String>> {
+    let file = args.get_one::<String>("file").unwrap();
+    let allow_other_actor = args.get_flag("any-actor");
+
+    let backup = match std::fs::read_to_string(file) {
+        Ok(backup) => backup,
+        Err(e) => return Ok(Some(format!("unable to read {file}: {e}"))),
+    };
+    let passphrase = match prompt("passphrase") {
+        Ok(passphrase) => passphrase,
+        Err(e) => return Ok(Some(e)),
+    };
+
+    Ok(Some(
+        match import_backup(backup, passphrase, Some(allow_other_actor)).await {
+            Ok(()) => format!("restored {file}"),
+            Err(e) => format!("{} [{}]", e, e.code()),
+        },
+    ))
+}
+
+// Checks a captured request: headers is a file containing a JSON object of
+// header names to values, body (if any) is a file containing the raw body
+async fn verify<T>(args: ArgMatches, _context: &mut T) -> Result<Option<String>> {
+    let headers = match std::fs::read(args.get_one::<String>("headers").unwrap()) {
+        Ok(headers) => headers,
+        Err(e) => return Ok(Some(format!("unable to read headers: {e}"))),
+    };
+
+    let headers: HashMap<String, String> = match serde_json::from_slice(&headers) {
+        Ok(headers) => headers,
+        Err(e) => return Ok(Some(format!("unable to parse headers: {e}"))),
+    };
+
+    let body = match args
+        .get_one::<String>("body")
+        .map(std::fs::read)
+        .transpose()
+    {
+        Ok(body) => body,
+        Err(e) => return Ok(Some(format!("unable to read body: {e}"))),
+    };
+
+    let params = VerifyParams {
+        method: args.get_one::<String>("method").unwrap().to_string(),
+        path: args.get_one::<String>("path").unwrap().to_string(),
+        headers: headers.into_iter().collect(),
+        body,
+    };
+
+    let actor = args.get_one::<String>("actor").cloned();
+
+    Ok(Some(match verify_with_actor(&params, actor).await {
+        Ok(verified) => format!(
+            "verified {:?} signature from {}",
+            verified.format, verified.key_id
+        ),
+        Err(e) => format!("{} [{}]", e, e.code()),
+    }))
 }
 
 #[tokio::main]
@@ -37,6 +144,40 @@ async fn main() -> Result<()> {
                 .arg(Arg::new("url").required(true))
                 .about("Load instance information"),
             |args, context| Box::pin(server(args, context)),
+        )
+        .with_command_async(
+            Command::new("verify")
+                .arg(Arg::new("method").required(true))
+                .arg(Arg::new("path").required(true))
+                .arg(Arg::new("headers").required(true))
+                .arg(Arg::new("body").long("body"))
+                .arg(Arg::new("actor").long("actor"))
+                .about("Verify the signature of a captured request"),
+            |args, context| Box::pin(verify(args, context)),
+        )
+        .with_command_async(
+            Command::new("login")
+                .arg(Arg::new("username").required(true))
+                .about("Log in to the loaded server"),
+            |args, context| Box::pin(login(args, context)),
+        )
+        .with_command_async(
+            Command::new("backup")
+                .arg(Arg::new("file").required(true))
+                .about("Write a passphrase-protected backup of the account keys"),
+            |args, context| Box::pin(backup(args, context)),
+        )
+        .with_command_async(
+            Command::new("restore")
+                .arg(Arg::new("file").required(true))
+                .arg(
+                    Arg::new("any-actor")
+                        .long("any-actor")
+                        .action(ArgAction::SetTrue)
+                        .help("Restore a backup made by another actor"),
+                )
+                .about("Restore a backup onto the logged-in account"),
+            |args, context| Box::pin(restore(args, context)),
         );
     repl.run_async().await
 }
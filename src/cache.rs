@@ -1,36 +1,78 @@
 use js_sys::Promise;
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::rc::{Rc, Weak};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::{future_to_promise, JsFuture};
 
+type CacheStore = RefCell<HashMap<String, CacheEntry>>;
+
+thread_local! {
+    // every live cache, so that logout can reach the ones JavaScript holds
+    static CACHES: RefCell<Vec<Weak<CacheStore>>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    promise: Promise,
+    // the actor the response was fetched as, if it was fetched with their
+    // credentials
+    owner: Option<String>,
+}
+
 #[wasm_bindgen]
 #[derive(Debug)]
 pub struct EnigmatickCache {
-    store: Mutex<HashMap<String, Promise>>,
+    store: Rc<CacheStore>,
 }
 
 #[wasm_bindgen]
 impl EnigmatickCache {
     #[wasm_bindgen(constructor)]
     pub fn new() -> Self {
-        EnigmatickCache {
-            store: Mutex::new(HashMap::new()),
-        }
+        let store = Rc::new(RefCell::new(HashMap::new()));
+
+        CACHES.with(|x| {
+            let mut caches = x.borrow_mut();
+            caches.retain(|x| x.strong_count() > 0);
+            caches.push(Rc::downgrade(&store));
+        });
+
+        EnigmatickCache { store }
     }
 
     pub fn get(&self, key: &str) -> Option<Promise> {
-        self.store.lock().ok()?.get(key).cloned()
+        self.store.borrow().get(key).map(|x| x.promise.clone())
     }
 
     pub fn set(&self, key: &str, value: Promise) {
-        self.store
-            .lock()
-            .map(|mut x| x.insert(key.to_string(), value))
-            .ok();
+        self.set_for(key, value, None);
     }
 }
 
+impl EnigmatickCache {
+    pub fn set_for(&self, key: &str, value: Promise, owner: Option<String>) {
+        self.store.borrow_mut().insert(
+            key.to_string(),
+            CacheEntry {
+                promise: value,
+                owner,
+            },
+        );
+    }
+}
+
+// Drops the entries fetched as owner from every cache
+pub fn clear_cached_for(owner: &str) {
+    CACHES.with(|x| {
+        for store in x.borrow().iter().filter_map(Weak::upgrade) {
+            store
+                .borrow_mut()
+                .retain(|_, entry| entry.owner.as_deref() != Some(owner));
+        }
+    });
+}
+
 #[wasm_bindgen]
 pub async fn fetch_with_cache(cache: &EnigmatickCache, url: String) -> Promise {
     if let Some(promise) = cache.get(&url.clone()) {
@@ -0,0 +1,255 @@
+use futures::future::{self, Either, LocalBoxFuture};
+use lazy_static::lazy_static;
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{current_account, sleep, EnigmatickError, EnigmatickResult};
+
+// applied to every request that does not set its own timeout; 0 disables it
+static DEFAULT_TIMEOUT_MS: AtomicU64 = AtomicU64::new(30_000);
+
+lazy_static! {
+    // every request is also tied to this token so that everything in flight
+    // can be abandoned at once (e.g., on navigation or logout)
+    static ref SESSION_TOKEN: Mutex<CancellationToken> = Mutex::new(CancellationToken::new());
+}
+
+thread_local! {
+    static CURRENT_OPTIONS: RefCell<Option<RequestOptions>> = const { RefCell::new(None) };
+}
+
+#[derive(Default, Debug)]
+struct TokenInner {
+    cancelled: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+// A cloneable handle that can be used to abandon requests. On wasm an
+// interrupted fetch is aborted through an AbortController; natively the
+// request future is dropped. JavaScript creates one with new
+// CancellationToken() and passes token.share() to the exports that take one,
+// as passing the token itself would consume it.
+#[wasm_bindgen]
+#[derive(Clone, Default, Debug)]
+pub struct CancellationToken {
+    inner: Arc<TokenInner>,
+}
+
+#[wasm_bindgen]
+impl CancellationToken {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+
+        if let Ok(mut wakers) = self.inner.wakers.lock() {
+            wakers.drain(..).for_each(Waker::wake);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    // another handle to the same token
+    pub fn share(&self) -> CancellationToken {
+        self.clone()
+    }
+}
+
+impl CancellationToken {
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            token: self.clone(),
+        }
+    }
+}
+
+pub struct Cancelled {
+    token: CancellationToken,
+}
+
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+
+        if let Ok(mut wakers) = self.token.inner.wakers.lock() {
+            if !wakers.iter().any(|x| x.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+
+        // cancel() may have run between the check above and registration
+        if self.token.is_cancelled() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct RequestOptions {
+    pub timeout_ms: Option<u64>,
+    pub cancellation: Option<CancellationToken>,
+    session: Option<CancellationToken>,
+    // cancelled when the account the request is made for logs out
+    account: Option<CancellationToken>,
+}
+
+impl RequestOptions {
+    pub fn new() -> Self {
+        RequestOptions::default()
+    }
+
+    pub fn timeout(mut self, ms: u64) -> Self {
+        self.timeout_ms = Some(ms);
+        self
+    }
+
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    // from the optional arguments of an export
+    pub fn of(timeout_ms: Option<u32>, cancellation: Option<CancellationToken>) -> Self {
+        RequestOptions {
+            timeout_ms: timeout_ms.map(u64::from),
+            cancellation,
+            ..RequestOptions::default()
+        }
+    }
+
+    // The options for a request being built now: those of the enclosing
+    // with_request_options scope (if any), tied to the current session token
+    pub fn current() -> Self {
+        let mut options = CURRENT_OPTIONS
+            .with(|x| x.borrow().clone())
+            .unwrap_or_default();
+
+        options.session = SESSION_TOKEN.lock().ok().map(|x| x.clone());
+        options.account = Some(current_account().session());
+        options
+    }
+
+    fn effective_timeout(&self) -> Option<u64> {
+        self.timeout_ms
+            .or_else(|| Some(DEFAULT_TIMEOUT_MS.load(Ordering::SeqCst)))
+            .filter(|x| *x > 0)
+    }
+
+    // Resolves with the error to report if the request is interrupted by its
+    // timeout or by cancellation; never resolves otherwise
+    pub fn interrupted(&self) -> LocalBoxFuture<'static, EnigmatickError> {
+        let mut waits: Vec<LocalBoxFuture<'static, EnigmatickError>> = vec![];
+
+        if let Some(ms) = self.effective_timeout() {
+            waits.push(Box::pin(async move {
+                sleep(ms).await;
+                EnigmatickError::Timeout(ms)
+            }));
+        }
+
+        for token in [&self.cancellation, &self.session, &self.account]
+            .into_iter()
+            .flatten()
+        {
+            let token = token.clone();
+            waits.push(Box::pin(async move {
+                token.cancelled().await;
+                EnigmatickError::Cancelled
+            }));
+        }
+
+        if waits.is_empty() {
+            Box::pin(future::pending())
+        } else {
+            Box::pin(async move { future::select_all(waits).await.0 })
+        }
+    }
+
+    // Runs f to completion unless the request is interrupted first
+    pub async fn guard<T>(
+        &self,
+        f: impl Future<Output = EnigmatickResult<T>>,
+    ) -> EnigmatickResult<T> {
+        if [&self.cancellation, &self.session, &self.account]
+            .into_iter()
+            .flatten()
+            .any(|x| x.is_cancelled())
+        {
+            return Err(EnigmatickError::Cancelled);
+        }
+
+        match future::select(Box::pin(f), self.interrupted()).await {
+            Either::Left((result, _)) => result,
+            Either::Right((error, _)) => Err(error),
+        }
+    }
+}
+
+// Runs f to completion unless token is cancelled first, in which case f is
+// dropped; for background work that is not a single request
+pub async fn unless_cancelled<F: Future>(token: &CancellationToken, f: F) -> Option<F::Output> {
+    match future::select(Box::pin(f), token.cancelled()).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+// Applies options to every request started while f is being polled
+pub fn with_request_options<F: Future>(
+    options: RequestOptions,
+    f: F,
+) -> impl Future<Output = F::Output> {
+    WithOptions {
+        options,
+        inner: Box::pin(f),
+    }
+}
+
+struct WithOptions<F: Future> {
+    options: RequestOptions,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for WithOptions<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let previous = CURRENT_OPTIONS.with(|x| x.replace(Some(self.options.clone())));
+        let result = self.inner.as_mut().poll(cx);
+        CURRENT_OPTIONS.with(|x| *x.borrow_mut() = previous);
+
+        result
+    }
+}
+
+// Sets the timeout used by requests that do not specify one (0 disables it)
+#[wasm_bindgen]
+pub fn set_request_timeout(ms: u32) {
+    DEFAULT_TIMEOUT_MS.store(ms as u64, Ordering::SeqCst);
+}
+
+// Abandons every request currently in flight; requests started afterward are
+// unaffected
+#[wasm_bindgen]
+pub fn cancel_all_requests() {
+    if let Ok(mut token) = SESSION_TOKEN.lock() {
+        let previous = std::mem::take(&mut *token);
+        previous.cancel();
+    }
+}
@@ -1,16 +1,14 @@
 use chrono::Utc;
 use serde_json::json;
 use uuid::Uuid;
-use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::{authenticated, log, send_post, EnigmatickState, Profile};
+use crate::{authenticated, log, send_activity, EnigmatickError, EnigmatickState, Profile};
 
-#[wasm_bindgen]
-pub async fn send_chess_invite(opponent_id: String) -> Option<String> {
-    authenticated(move |state: EnigmatickState, profile: Profile| async move {
+pub async fn send_chess_invite(opponent_id: String) -> Result<String, EnigmatickError> {
+    authenticated(move |_state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
 
-        let actor_id = state.profile?.id.clone();
+        let actor_id = profile.id.clone();
         let activity_id = format!("{}/activity/invite/{}", actor_id, Uuid::new_v4());
 
         // Create Invite activity per ActivityStreams vocabulary
@@ -32,22 +30,16 @@ pub async fn send_chess_invite(opponent_id: String) -> Option<String> {
 
         log(&format!("CHESS INVITE\n{}", serde_json::to_string_pretty(&invite_activity).unwrap()));
 
-        send_post(
-            outbox,
-            serde_json::to_string(&invite_activity).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &invite_activity).await
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn send_chess_accept(game_id: String) -> Option<String> {
-    authenticated(move |state: EnigmatickState, profile: Profile| async move {
+pub async fn send_chess_accept(game_id: String) -> Result<String, EnigmatickError> {
+    authenticated(move |_state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
 
-        let actor_id = state.profile?.id.clone();
+        let actor_id = profile.id.clone();
         let activity_id = format!("{}/activity/accept/{}", actor_id, Uuid::new_v4());
 
         // Create Accept activity per ActivityStreams vocabulary
@@ -64,27 +56,21 @@ pub async fn send_chess_accept(game_id: String) -> Option<String> {
 
         log(&format!("CHESS ACCEPT\n{}", serde_json::to_string_pretty(&accept_activity).unwrap()));
 
-        send_post(
-            outbox,
-            serde_json::to_string(&accept_activity).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &accept_activity).await
     })
     .await
 }
 
-#[wasm_bindgen]
 pub async fn send_chess_move(
     game_id: String,
     from: String,
     to: String,
     promotion: Option<String>,
-) -> Option<String> {
-    authenticated(move |state: EnigmatickState, profile: Profile| async move {
+) -> Result<String, EnigmatickError> {
+    authenticated(move |_state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
 
-        let actor_id = state.profile?.id.clone();
+        let actor_id = profile.id.clone();
         let activity_id = format!("{}/activity/move/{}", actor_id, Uuid::new_v4());
 
         // Build move object with custom properties
@@ -126,22 +112,16 @@ pub async fn send_chess_move(
 
         log(&format!("CHESS MOVE\n{}", serde_json::to_string_pretty(&move_activity).unwrap()));
 
-        send_post(
-            outbox,
-            serde_json::to_string(&move_activity).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &move_activity).await
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn send_chess_resign(game_id: String) -> Option<String> {
-    authenticated(move |state: EnigmatickState, profile: Profile| async move {
+pub async fn send_chess_resign(game_id: String) -> Result<String, EnigmatickError> {
+    authenticated(move |_state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
 
-        let actor_id = state.profile?.id.clone();
+        let actor_id = profile.id.clone();
         let activity_id = format!("{}/activity/update/{}", actor_id, Uuid::new_v4());
 
         // Create Update activity to resign the game
@@ -163,12 +143,7 @@ pub async fn send_chess_resign(game_id: String) -> Option<String> {
 
         log(&format!("CHESS RESIGN\n{}", serde_json::to_string_pretty(&update_activity).unwrap()));
 
-        send_post(
-            outbox,
-            serde_json::to_string(&update_activity).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &update_activity).await
     })
     .await
 }
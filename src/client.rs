@@ -0,0 +1,805 @@
+use js_sys::Promise;
+use std::future::Future;
+use std::sync::Arc;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    current_account, get_account, get_transport, in_account, with_account, with_transport, Account,
+    ArticleParams, CancellationToken, EnigmatickError, EnigmatickResult, EnigmatickState,
+    InstanceInformation, KeyAlgorithm, NoteParams, Profile, QuestionParams, RequestOptions,
+    SignatureFormat, Transport,
+};
+
+// A Client owns an account (state, key material and what is known about its
+// server) and the transport it talks through, so that any number of them can
+// be used in one process without touching each other. Every method runs the
+// function of the same name confined to the client. The exports (exports.rs)
+// are these methods on the default client: the active account (see
+// account.rs) over the transport from set_transport. JavaScript gets a Client
+// from for_account to act on a signed-in account other than the active one.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct Client {
+    account: Arc<Account>,
+    transport: Arc<dyn Transport>,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client::new()
+    }
+}
+
+impl Client {
+    pub fn with_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    // Keeps the account's persisted data (e.g., the outbox queue) under dir
+    // rather than storage_home()
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_storage_dir(self, dir: std::path::PathBuf) -> Self {
+        if let Ok(mut storage_dir) = self.account.storage_dir.lock() {
+            *storage_dir = Some(dir);
+        }
+        self
+    }
+
+    pub fn account(&self) -> Arc<Account> {
+        self.account.clone()
+    }
+
+    // Runs f against this client's account and transport; for anything that
+    // does not have a method here
+    pub fn run<F: Future>(&self, f: F) -> impl Future<Output = F::Output> {
+        with_account(
+            self.account.clone(),
+            with_transport(self.transport.clone(), f),
+        )
+    }
+
+    // The synchronous counterpart of run; only the account applies
+    pub fn enter<T>(&self, f: impl FnOnce() -> T) -> T {
+        in_account(self.account.clone(), f)
+    }
+
+    pub async fn get_actor_with_options(
+        &self,
+        id: String,
+        options: RequestOptions,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::get_actor_with_options(id, options)).await
+    }
+}
+
+#[wasm_bindgen]
+impl Client {
+    // Starts out with empty state; call load_instance_information and
+    // authenticate (or create_user) before anything else
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Client {
+            account: Arc::new(Account::detached()),
+            transport: get_transport(),
+        }
+    }
+
+    // The default client, as it is at the time of the call
+    pub fn active() -> Self {
+        Client {
+            account: current_account(),
+            transport: get_transport(),
+        }
+    }
+
+    // A signed-in account (see list_accounts), whether or not it is active
+    pub fn for_account(actor_id: String) -> EnigmatickResult<Client> {
+        let account = get_account(&actor_id)
+            .ok_or(EnigmatickError::missing(&format!("account {actor_id}")))?;
+
+        Ok(Client {
+            account,
+            transport: get_transport(),
+        })
+    }
+
+    pub fn state(&self) -> EnigmatickState {
+        self.account
+            .state
+            .lock()
+            .map(|x| x.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn profile(&self) -> Option<Profile> {
+        self.state().profile
+    }
+
+    pub async fn load_instance_information(
+        &self,
+        url: Option<String>,
+    ) -> EnigmatickResult<InstanceInformation> {
+        self.run(crate::load_instance_information(url)).await
+    }
+
+    pub async fn authenticate(
+        &self,
+        username: String,
+        password_str: String,
+    ) -> EnigmatickResult<Profile> {
+        self.run(crate::authenticate(username, password_str)).await
+    }
+
+    pub async fn authenticate_legacy(
+        &self,
+        username: String,
+        password_str: String,
+    ) -> EnigmatickResult<Profile> {
+        self.run(crate::authenticate_legacy(username, password_str))
+            .await
+    }
+
+    pub async fn logout(&self) -> EnigmatickResult<()> {
+        self.run(crate::logout()).await
+    }
+
+    pub async fn create_user(
+        &self,
+        username: String,
+        display_name: String,
+        password_str: String,
+        key_algorithm: Option<KeyAlgorithm>,
+        recovery_phrase: Option<String>,
+    ) -> EnigmatickResult<Profile> {
+        self.run(crate::create_user(
+            username,
+            display_name,
+            password_str,
+            key_algorithm,
+            recovery_phrase,
+        ))
+        .await
+    }
+
+    pub async fn upload_image(&self, data: &[u8]) -> EnigmatickResult<String> {
+        self.run(crate::upload_image(data)).await
+    }
+
+    pub async fn upload_avatar(&self, data: &[u8], extension: String) -> EnigmatickResult<()> {
+        self.run(crate::upload_avatar(data, extension)).await
+    }
+
+    pub async fn upload_banner(&self, data: &[u8], extension: String) -> EnigmatickResult<()> {
+        self.run(crate::upload_banner(data, extension)).await
+    }
+
+    pub async fn update_password(
+        &self,
+        current_str: String,
+        updated_str: String,
+    ) -> EnigmatickResult<bool> {
+        self.run(crate::update_password(current_str, updated_str))
+            .await
+    }
+
+    pub async fn rotate_client_key(
+        &self,
+        key_algorithm: Option<KeyAlgorithm>,
+        grace_period: Option<u32>,
+    ) -> EnigmatickResult<Profile> {
+        self.run(crate::rotate_client_key(key_algorithm, grace_period))
+            .await
+    }
+
+    pub async fn update_summary(
+        &self,
+        summary: String,
+        markdown: String,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::update_summary(summary, markdown)).await
+    }
+
+    pub async fn get_ap_id(&self) -> EnigmatickResult<String> {
+        self.run(crate::get_ap_id()).await
+    }
+
+    pub async fn get_webfinger(&self) -> EnigmatickResult<String> {
+        self.run(crate::get_webfinger()).await
+    }
+
+    pub async fn get_followers(
+        &self,
+        username: String,
+        page: Option<u32>,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::get_followers(username, page)).await
+    }
+
+    pub async fn get_following(
+        &self,
+        username: String,
+        page: Option<u32>,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::get_following(username, page)).await
+    }
+
+    pub async fn get_profile_by_username(&self, username: String) -> EnigmatickResult<String> {
+        self.run(crate::get_profile_by_username(username)).await
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.enter(crate::is_locked)
+    }
+
+    pub fn lock(&self) {
+        self.enter(crate::lock)
+    }
+
+    pub fn note_activity(&self) {
+        self.enter(crate::note_activity)
+    }
+
+    pub fn set_idle_timeout(&self, seconds: u32) {
+        self.enter(|| crate::set_idle_timeout(seconds))
+    }
+
+    pub fn unlock(&self, password: String) -> EnigmatickResult<()> {
+        self.enter(|| crate::unlock(password))
+    }
+
+    pub async fn persist_state(&self) -> EnigmatickResult<()> {
+        self.run(crate::persist_state()).await
+    }
+
+    pub async fn persisted_username(&self) -> EnigmatickResult<Option<String>> {
+        self.run(crate::persisted_username()).await
+    }
+
+    pub async fn restore_state(&self, password: String) -> EnigmatickResult<bool> {
+        self.run(crate::restore_state(password)).await
+    }
+
+    pub async fn clear_persisted_state(&self) -> EnigmatickResult<()> {
+        self.run(crate::clear_persisted_state()).await
+    }
+
+    pub async fn add_recovery_phrase(&self, phrase: String) -> EnigmatickResult<()> {
+        self.run(crate::add_recovery_phrase(phrase)).await
+    }
+
+    pub async fn recover_account(
+        &self,
+        username: String,
+        phrase: String,
+        password_str: String,
+    ) -> EnigmatickResult<Profile> {
+        self.run(crate::recover_account(username, phrase, password_str))
+            .await
+    }
+
+    pub async fn export_backup(&self, passphrase: String) -> EnigmatickResult<String> {
+        self.run(crate::export_backup(passphrase)).await
+    }
+
+    pub async fn import_backup(
+        &self,
+        backup: String,
+        passphrase: String,
+        allow_other_actor: Option<bool>,
+    ) -> EnigmatickResult<()> {
+        self.run(crate::import_backup(backup, passphrase, allow_other_actor))
+            .await
+    }
+
+    pub async fn get_timeline(
+        &self,
+        max: Option<String>,
+        min: Option<String>,
+        limit: i32,
+        view: String,
+        hashtags: Vec<String>,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::get_timeline_with_hashtags(
+            max, min, limit, view, hashtags,
+        ))
+        .await
+    }
+    pub async fn get_conversation(
+        &self,
+        conversation: String,
+        limit: i32,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::get_conversation(conversation, limit)).await
+    }
+
+    pub async fn get_inbox(&self, offset: i32, limit: i32) -> EnigmatickResult<String> {
+        self.run(crate::get_inbox(offset, limit)).await
+    }
+
+    pub async fn get_outbox(
+        &self,
+        username: String,
+        kind: Option<String>,
+        timestamp: Option<String>,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::get_outbox(username, kind, timestamp)).await
+    }
+
+    pub async fn get_remote_resource(
+        &self,
+        resource: String,
+        webfinger: String,
+        page: Option<String>,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::get_remote_resource(resource, webfinger, page))
+            .await
+    }
+
+    pub async fn get_remote_following(
+        &self,
+        webfinger: String,
+        page: Option<String>,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::get_remote_following(webfinger, page)).await
+    }
+
+    pub async fn get_remote_followers(
+        &self,
+        webfinger: String,
+        page: Option<String>,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::get_remote_followers(webfinger, page)).await
+    }
+
+    pub async fn get_remote_outbox(
+        &self,
+        webfinger: String,
+        page: Option<String>,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::get_remote_outbox(webfinger, page)).await
+    }
+
+    // timeout_ms and cancellation apply to both the webfinger and actor lookups
+    pub async fn get_actor(
+        &self,
+        id: String,
+        timeout_ms: Option<u32>,
+        cancellation: Option<CancellationToken>,
+    ) -> EnigmatickResult<String> {
+        self.get_actor_with_options(id, RequestOptions::of(timeout_ms, cancellation))
+            .await
+    }
+
+    pub fn get_actor_from_webfinger_promise(
+        &self,
+        webfinger: String,
+        timeout_ms: Option<u32>,
+        cancellation: Option<CancellationToken>,
+    ) -> Promise {
+        self.enter(|| crate::get_actor_from_webfinger_promise(webfinger, timeout_ms, cancellation))
+    }
+    pub async fn get_webfinger_from_id(&self, id: String) -> EnigmatickResult<String> {
+        self.run(crate::get_webfinger_from_id(id)).await
+    }
+
+    pub async fn get_webfinger_from_handle(&self, handle: String) -> EnigmatickResult<String> {
+        self.run(crate::get_webfinger_from_handle(handle)).await
+    }
+
+    pub async fn get_local_conversation(&self, uuid: String) -> EnigmatickResult<String> {
+        self.run(crate::get_local_conversation(uuid)).await
+    }
+
+    pub async fn get_note(&self, id: String) -> EnigmatickResult<String> {
+        self.run(crate::get_note(id)).await
+    }
+
+    pub async fn send_note(&self, params: &mut NoteParams) -> EnigmatickResult<String> {
+        self.run(crate::send_note(params)).await
+    }
+
+    pub async fn send_vote(
+        &self,
+        option_name: String,
+        question_id: String,
+        question_author: String,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::send_vote(option_name, question_id, question_author))
+            .await
+    }
+
+    pub async fn send_question(&self, question_json: String) -> EnigmatickResult<String> {
+        self.run(crate::send_question(question_json)).await
+    }
+
+    pub async fn send_follow(&self, address: String) -> EnigmatickResult<String> {
+        self.run(crate::send_follow(address)).await
+    }
+
+    pub async fn send_unfollow(&self, address: String, id: String) -> EnigmatickResult<String> {
+        self.run(crate::send_unfollow(address, id)).await
+    }
+
+    pub async fn send_like(&self, to: String, object: String) -> EnigmatickResult<String> {
+        self.run(crate::send_like(to, object)).await
+    }
+
+    pub async fn send_unlike(
+        &self,
+        to: String,
+        object: String,
+        id: String,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::send_unlike(to, object, id)).await
+    }
+
+    pub async fn send_announce(&self, object: String) -> EnigmatickResult<String> {
+        self.run(crate::send_announce(object)).await
+    }
+
+    pub async fn send_unannounce(&self, object: String, id: String) -> EnigmatickResult<String> {
+        self.run(crate::send_unannounce(object, id)).await
+    }
+
+    pub async fn send_delete(&self, object: String) -> EnigmatickResult<bool> {
+        self.run(crate::send_delete(object)).await
+    }
+
+    pub async fn send_update(
+        &self,
+        object_id: String,
+        updated_object_json: String,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::send_update(object_id, updated_object_json))
+            .await
+    }
+
+    pub async fn send_update_note(&self, params: &mut NoteParams) -> EnigmatickResult<String> {
+        self.run(crate::send_update_note(params)).await
+    }
+
+    pub async fn send_update_article(
+        &self,
+        params: &mut ArticleParams,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::send_update_article(params)).await
+    }
+
+    pub async fn send_update_question(
+        &self,
+        params: &mut QuestionParams,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::send_update_question(params)).await
+    }
+
+    pub async fn send_chess_invite(&self, opponent_id: String) -> EnigmatickResult<String> {
+        self.run(crate::send_chess_invite(opponent_id)).await
+    }
+
+    pub async fn send_chess_accept(&self, game_id: String) -> EnigmatickResult<String> {
+        self.run(crate::send_chess_accept(game_id)).await
+    }
+
+    pub async fn send_chess_move(
+        &self,
+        game_id: String,
+        from: String,
+        to: String,
+        promotion: Option<String>,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::send_chess_move(game_id, from, to, promotion))
+            .await
+    }
+
+    pub async fn send_chess_resign(&self, game_id: String) -> EnigmatickResult<String> {
+        self.run(crate::send_chess_resign(game_id)).await
+    }
+
+    pub async fn upload_encrypted_attachment(
+        &self,
+        data: &[u8],
+        media_type: String,
+        name: Option<String>,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::upload_encrypted_attachment(data, media_type, name))
+            .await
+    }
+
+    pub fn open_encrypted_message(&self, message: String) -> EnigmatickResult<String> {
+        self.enter(|| crate::open_encrypted_message(message))
+    }
+
+    pub async fn decrypt_attachment(&self, attachment: String) -> EnigmatickResult<Vec<u8>> {
+        self.run(crate::decrypt_attachment(attachment)).await
+    }
+
+    pub async fn store_to_vault(
+        &self,
+        data: String,
+        remote_actor: String,
+        resolves: String,
+        session_uuid: String,
+        session: String,
+        mutation_of: String,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::store_to_vault(
+            data,
+            remote_actor,
+            resolves,
+            session_uuid,
+            session,
+            mutation_of,
+        ))
+        .await
+    }
+
+    pub async fn get_vault(
+        &self,
+        offset: i32,
+        limit: i32,
+        actor: String,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::get_vault(offset, limit, actor)).await
+    }
+
+    pub async fn send_authorization(&self, uuid: String) -> EnigmatickResult<bool> {
+        self.run(crate::send_authorization(uuid)).await
+    }
+
+    pub async fn replenish_mkp(&self) -> EnigmatickResult<bool> {
+        self.run(crate::mls::replenish_mkp()).await
+    }
+
+    pub async fn resolve_processed_item(&self, id: String) -> EnigmatickResult<String> {
+        self.run(crate::resolve_processed_item(id)).await
+    }
+
+    pub async fn get_processing_queue(&self) -> EnigmatickResult<String> {
+        self.run(crate::get_processing_queue()).await
+    }
+
+    pub async fn drain_outbox_queue(&self) -> EnigmatickResult<u32> {
+        self.run(crate::drain_outbox_queue()).await
+    }
+
+    pub async fn get_outbox_queue(&self) -> EnigmatickResult<String> {
+        self.run(crate::get_outbox_queue()).await
+    }
+
+    pub async fn reorder_outbox_queue(&self, ids: Vec<String>) -> EnigmatickResult<()> {
+        self.run(crate::reorder_outbox_queue(ids)).await
+    }
+
+    pub async fn cancel_queued_activity(&self, id: String) -> EnigmatickResult<bool> {
+        self.run(crate::cancel_queued_activity(id)).await
+    }
+
+    pub fn set_signature_format(&self, host: String, format: SignatureFormat) {
+        self.enter(|| crate::set_signature_format(host, format))
+    }
+
+    pub fn set_kdf_params(&self, iterations: u32, memory_kib: u32) -> EnigmatickResult<()> {
+        self.enter(|| crate::set_kdf_params(iterations, memory_kib))
+    }
+
+    pub fn decrypt_text(&self, encoded_data: String) -> EnigmatickResult<String> {
+        self.enter(|| crate::decrypt_text(encoded_data))
+    }
+
+    pub fn get_clock_offset(&self) -> f64 {
+        self.enter(crate::get_clock_offset)
+    }
+
+    pub async fn verify_signature(
+        &self,
+        method: String,
+        path: String,
+        headers: String,
+        body: Option<String>,
+        actor: Option<String>,
+    ) -> EnigmatickResult<String> {
+        self.run(crate::verify_signature(method, path, headers, body, actor))
+            .await
+    }
+
+    pub fn get_activity_ap_id_from_uuid(&self, uuid: String) -> EnigmatickResult<String> {
+        self.enter(|| crate::get_activity_ap_id_from_uuid(uuid))
+    }
+}
+
+// Keeps work that outlives the current call (e.g., a spawned task) on the
+// client that started it
+pub fn with_current_client<F: Future>(f: F) -> impl Future<Output = F::Output> {
+    Client::active().run(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generate_client_key, get_signature_format, update_state, HttpResponse, KdfParams, Method,
+        MockTransport, DEFAULT_KDF_ITERATIONS,
+    };
+    use futures::executor::block_on;
+    use serde_json::Value;
+
+    const SERVER: &str = "https://example.com";
+
+    // as served by /inbox?view=global
+    const TIMELINE: &str = r#"{
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": "OrderedCollection",
+        "id": "https://example.com/inbox?limit=10&view=global",
+        "totalItems": 1,
+        "orderedItems": [
+            {
+                "@context": "https://www.w3.org/ns/activitystreams",
+                "type": "Create",
+                "id": "https://example.com/activities/9b5e5c2a-2f3f-4b7c-9d1e-3a6f0c1d2e4b",
+                "actor": "https://example.com/user/bob",
+                "to": ["https://www.w3.org/ns/activitystreams#Public"],
+                "object": {
+                    "type": "Note",
+                    "id": "https://example.com/objects/5f0c8a7e-6d1b-4c2a-8e3f-1b2c3d4e5f60",
+                    "attributedTo": "https://example.com/user/bob",
+                    "to": ["https://www.w3.org/ns/activitystreams#Public"],
+                    "content": "<p>hello from bob</p>",
+                    "published": "2024-05-01T12:00:00Z"
+                }
+            }
+        ]
+    }"#;
+
+    fn client(transport: &Arc<MockTransport>) -> Client {
+        let client = Client::new().with_transport(transport.clone());
+
+        client
+            .enter(|| {
+                update_state(|state| {
+                    state.server_name = Some("example.com".to_string());
+                    state.server_url = Some(SERVER.to_string());
+                    Ok(())
+                })
+            })
+            .unwrap();
+
+        client
+    }
+
+    // Signed in as alice, with a client key to sign with
+    fn signed_in(transport: &Arc<MockTransport>) -> Client {
+        let client = client(transport);
+        let key = generate_client_key(KeyAlgorithm::Ed25519).unwrap();
+
+        client
+            .enter(|| {
+                update_state(|state| {
+                    state.authenticated = true;
+                    state.set_profile(Profile {
+                        username: "alice".to_string(),
+                        id: format!("{SERVER}/user/alice").into(),
+                        ..Profile::default()
+                    });
+                    state.set_client_private_key_pem(key.private_key);
+                    Ok(())
+                })
+            })
+            .unwrap();
+
+        client
+    }
+
+    #[test]
+    fn get_timeline_returns_the_recorded_collection() {
+        let transport = Arc::new(MockTransport::new());
+        transport.respond(
+            Method::Get,
+            "/inbox?limit=10&view=global",
+            HttpResponse::new(200, TIMELINE),
+        );
+
+        let timeline =
+            block_on(client(&transport).get_timeline(None, None, 10, "global".to_string(), vec![]))
+                .unwrap();
+
+        let timeline: Value = serde_json::from_str(&timeline).unwrap();
+        assert_eq!(
+            timeline["orderedItems"][0]["object"]["content"],
+            "<p>hello from bob</p>"
+        );
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].url,
+            format!("{SERVER}/inbox?limit=10&view=global")
+        );
+    }
+
+    #[test]
+    fn send_note_posts_a_signed_note_to_the_outbox() {
+        // the outbox queue is consulted after a successful send
+        let dir = std::env::temp_dir().join(format!("enigmatick-client-{}", uuid::Uuid::new_v4()));
+
+        let transport = Arc::new(MockTransport::new());
+        transport.respond(
+            Method::Post,
+            "/user/alice/outbox",
+            HttpResponse::new(202, ""),
+        );
+
+        let client = signed_in(&transport).with_storage_dir(dir.clone());
+        let mut params = block_on(client.run(NoteParams::new()));
+        params.set_content("<p>hello</p>".to_string());
+        params.set_public();
+
+        block_on(client.send_note(&mut params)).unwrap();
+
+        let requests = transport.requests();
+        let request = requests
+            .iter()
+            .find(|x| matches!(x.method, Method::Post) && x.path() == "/user/alice/outbox")
+            .unwrap();
+
+        let note: Value = serde_json::from_slice(request.body.as_ref().unwrap()).unwrap();
+        assert_eq!(note["type"], "Note");
+        assert_eq!(note["content"], "<p>hello</p>");
+        assert_eq!(note["attributedTo"], format!("{SERVER}/user/alice"));
+        assert!(note["id"]
+            .as_str()
+            .unwrap()
+            .starts_with("https://example.com/objects/"));
+
+        let header = |name: &str| {
+            request
+                .headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+        assert!(header("Signature").is_some_and(|x| x.contains("#client-key")));
+        assert!(header("Digest").is_some());
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn authenticate_never_sends_the_password_hash() {
+        let transport = Arc::new(MockTransport::new());
+        transport.respond(
+            Method::Post,
+            "/api/user/authenticate/start",
+            HttpResponse::new(200, r#"{"legacy":true}"#),
+        );
+
+        let client = client(&transport);
+        assert!(block_on(client.authenticate("alice".to_string(), "secret".to_string())).is_err());
+        assert!(!transport
+            .requests()
+            .iter()
+            .any(|x| x.path() == "/api/user/authenticate"));
+    }
+
+    #[test]
+    fn clients_keep_their_own_settings() {
+        let (a, b) = (Client::new(), Client::new());
+
+        a.set_signature_format("example.com".to_string(), SignatureFormat::Rfc9421);
+        a.set_kdf_params(4, 1 << 10).unwrap();
+
+        assert_eq!(
+            a.enter(|| get_signature_format("example.com")),
+            SignatureFormat::Rfc9421
+        );
+        assert_eq!(
+            b.enter(|| get_signature_format("example.com")),
+            SignatureFormat::Cavage
+        );
+        assert_eq!(a.enter(KdfParams::current).iterations, 4);
+        assert_eq!(
+            b.enter(KdfParams::current).iterations,
+            DEFAULT_KDF_ITERATIONS
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::atomic::Ordering;
+
+use crate::{current_account, log, send_request, EnigmatickResult, HttpRequest, HttpResponse};
+
+// Signed requests carry the time they were signed, and servers reject those
+// that fall outside of their window. The local clock cannot be trusted to be
+// right, so the offset to the server's clock is measured from the Date header
+// of every response and applied to the time used for signing. Each account
+// keeps its own offset, as accounts may be on different servers.
+
+// Date has one-second resolution; differences smaller than that are noise
+const DATE_RESOLUTION_MS: i64 = 1_000;
+
+// The local time in milliseconds since the epoch; Date.now() is only there
+// in JavaScript
+#[cfg(target_arch = "wasm32")]
+pub fn local_now() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn local_now() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|x| x.as_millis() as f64)
+        .unwrap_or_default()
+}
+
+pub fn clock_offset() -> i64 {
+    current_account().clock_offset.load(Ordering::SeqCst)
+}
+
+// The current time according to the server, in milliseconds since the epoch
+pub fn server_now() -> f64 {
+    local_now() + clock_offset() as f64
+}
+
+// Browsers only expose Date to cross-origin callers when the server lists it
+// in Access-Control-Expose-Headers; without it, the offset stays where it is
+pub fn note_server_date(response: &HttpResponse) {
+    let Some(date) = response
+        .header("Date")
+        .and_then(|x| httpdate::parse_http_date(x.trim()).ok())
+    else {
+        return;
+    };
+
+    let Ok(server_ms) = date.duration_since(std::time::UNIX_EPOCH) else {
+        return;
+    };
+
+    // the header is truncated to the second, so assume the middle of it
+    let offset = (server_ms.as_millis() as f64 + 500.0 - local_now()) as i64;
+
+    if (offset - clock_offset()).abs() > DATE_RESOLUTION_MS {
+        current_account()
+            .clock_offset
+            .store(offset, Ordering::SeqCst);
+        log(&format!("clock offset to server is now {offset}ms"));
+    }
+}
+
+// A signed request was refused, and the response moved our idea of the
+// server's time since the request was signed: the signature date was likely
+// outside of the server's window and signing again would help
+fn is_clock_rejection(response: &HttpResponse, signed_offset: i64) -> bool {
+    matches!(response.status, 401 | 403) && clock_offset() != signed_offset
+}
+
+// Sends a request built (and signed) by build. If the server rejects it
+// because our clock was off, the request is signed again using the resynced
+// clock and retried once.
+pub async fn send_signed<F>(build: F) -> EnigmatickResult<HttpResponse>
+where
+    F: Fn() -> EnigmatickResult<HttpRequest>,
+{
+    let signed_offset = clock_offset();
+    let response = send_request(build()?).await?;
+
+    if is_clock_rejection(&response, signed_offset) {
+        log(&format!(
+            "signed request rejected with {} after clock resync; retrying",
+            response.status
+        ));
+        return send_request(build()?).await;
+    }
+
+    Ok(response)
+}
+
+// The measured offset (in milliseconds) between the server's clock and ours,
+// for showing a warning when the local clock is far off
+pub fn get_clock_offset() -> f64 {
+    clock_offset() as f64
+}
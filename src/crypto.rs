@@ -1,16 +1,22 @@
-use anyhow::{anyhow, Result};
 use base64::{engine::general_purpose, engine::Engine as _};
 use orion::hash::digest;
 use orion::kdf;
 use orion::{aead, aead::SecretKey};
 use rsa::pkcs1v15::Signature;
+use rsa::pkcs8::{EncodePrivateKey, EncodePublicKey, LineEnding};
 use rsa::signature::{RandomizedSigner, SignatureEncoding};
 use rsa::{pkcs1v15::SigningKey, pkcs8::DecodePrivateKey, RsaPrivateKey, RsaPublicKey};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::error::Error;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use wasm_bindgen::prelude::wasm_bindgen;
+use zeroize::Zeroizing;
 
-use crate::{date_now, get_state, EnigmatickState, Method, Profile};
+use crate::{
+    current_account, get_state, send_post, server_now, update_state, with_secrets, EnigmatickError,
+    EnigmatickResult, EnigmatickState, HttpResponse, Method, Profile,
+};
 
 pub struct KeyPair {
     pub private_key: RsaPrivateKey,
@@ -30,10 +36,153 @@ pub fn get_key_pair() -> KeyPair {
 }
 
 #[wasm_bindgen]
-pub fn get_hash(data: Vec<u8>) -> Option<String> {
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyAlgorithm {
+    // RSA-2048 (PKCS#1 v1.5 signatures) is accepted by every server, but takes
+    // seconds to generate in wasm
+    #[default]
+    Rsa,
+    Ed25519,
+}
+
+// PEM-encoded client key pair: SPKI public key and PKCS#8 private key
+pub struct ClientKeyPem {
+    pub public_key: String,
+    pub private_key: String,
+}
+
+pub fn generate_client_key(algorithm: KeyAlgorithm) -> EnigmatickResult<ClientKeyPem> {
+    match algorithm {
+        KeyAlgorithm::Rsa => {
+            let key = get_key_pair();
+
+            Ok(ClientKeyPem {
+                public_key: key
+                    .public_key
+                    .to_public_key_pem(LineEnding::default())
+                    .map_err(EnigmatickError::encryption)?,
+                private_key: key
+                    .private_key
+                    .to_pkcs8_pem(LineEnding::default())
+                    .map_err(EnigmatickError::encryption)?
+                    .to_string(),
+            })
+        }
+        KeyAlgorithm::Ed25519 => {
+            let key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+
+            Ok(ClientKeyPem {
+                public_key: key
+                    .verifying_key()
+                    .to_public_key_pem(LineEnding::default())
+                    .map_err(EnigmatickError::encryption)?,
+                private_key: key
+                    .to_pkcs8_pem(LineEnding::default())
+                    .map_err(EnigmatickError::encryption)?
+                    .to_string(),
+            })
+        }
+    }
+}
+
+// The client private key, parsed and ready to sign with
+pub enum ClientSigningKey {
+    Rsa(SigningKey<Sha256>),
+    Ed25519(ed25519_dalek::SigningKey),
+}
+
+impl ClientSigningKey {
+    pub fn from_pem(private_key_pem: &str) -> EnigmatickResult<Self> {
+        // both are PKCS#8 "PRIVATE KEY" documents; the Ed25519 parse is cheap
+        // and fails fast on an RSA key, so it goes first
+        if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_pem(private_key_pem) {
+            return Ok(ClientSigningKey::Ed25519(key));
+        }
+
+        RsaPrivateKey::from_pkcs8_pem(private_key_pem)
+            .map(|x| ClientSigningKey::Rsa(SigningKey::<Sha256>::new(x)))
+            .map_err(|e| EnigmatickError::Internal(format!("invalid client key: {e}")))
+    }
+
+    pub fn public_key_pem(&self) -> EnigmatickResult<String> {
+        match self {
+            ClientSigningKey::Rsa(signing_key) => {
+                let private_key: &RsaPrivateKey = signing_key.as_ref();
+                RsaPublicKey::from(private_key).to_public_key_pem(LineEnding::default())
+            }
+            ClientSigningKey::Ed25519(signing_key) => signing_key
+                .verifying_key()
+                .to_public_key_pem(LineEnding::default()),
+        }
+        .map_err(EnigmatickError::encryption)
+    }
+
+    pub fn algorithm(&self) -> KeyAlgorithm {
+        match self {
+            ClientSigningKey::Rsa(_) => KeyAlgorithm::Rsa,
+            ClientSigningKey::Ed25519(_) => KeyAlgorithm::Ed25519,
+        }
+    }
+
+    // Returns the base64-encoded signature
+    pub fn sign(&self, signed_string: &str) -> String {
+        let signature = match self {
+            ClientSigningKey::Rsa(signing_key) => {
+                let mut rng = rand::thread_rng();
+                let signature: Signature =
+                    signing_key.sign_with_rng(&mut rng, signed_string.as_bytes());
+                signature.to_vec()
+            }
+            ClientSigningKey::Ed25519(signing_key) => {
+                use ed25519_dalek::Signer;
+                signing_key
+                    .sign(signed_string.as_bytes())
+                    .to_bytes()
+                    .to_vec()
+            }
+        };
+
+        general_purpose::STANDARD.encode(signature)
+    }
+}
+
+// The key material itself is never printed
+impl std::fmt::Debug for ClientSigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ClientSigningKey")
+            .field(&self.algorithm())
+            .finish()
+    }
+}
+
+// Parsing an RSA key is slow in wasm, so each account keeps its parsed key
+// and only replaces it when the PEM in its state changes (identified by its
+// hash); the key is dropped along with the secrets (see Account::clear and
+// lock)
+pub fn client_signing_key(private_key_pem: &str) -> EnigmatickResult<Arc<ClientSigningKey>> {
+    let fingerprint = Sha256::digest(private_key_pem.as_bytes()).to_vec();
+    let account = current_account();
+
+    if let Ok(cached) = account.signing_key.lock() {
+        if let Some((_, key)) = cached.as_ref().filter(|(x, _)| *x == fingerprint) {
+            return Ok(key.clone());
+        }
+    }
+
+    let key = Arc::new(ClientSigningKey::from_pem(private_key_pem)?);
+
+    if let Ok(mut cached) = account.signing_key.lock() {
+        *cached = Some((fingerprint, key.clone()));
+    }
+
+    Ok(key)
+}
+
+#[wasm_bindgen]
+pub fn get_hash(data: Vec<u8>) -> Result<String, EnigmatickError> {
     digest(&data)
-        .ok()
         .map(|x| general_purpose::STANDARD.encode(x))
+        .map_err(EnigmatickError::encryption)
 }
 
 #[derive(Clone, Debug)]
@@ -49,10 +198,51 @@ pub struct SignParams {
 pub struct SignResponse {
     pub signature: String,
     pub date: String,
+    // legacy Digest header (sha-256=...) covered by draft-cavage signatures
     pub digest: Option<String>,
+    // RFC 9530 Content-Digest header (sha-256=:...:)
+    pub content_digest: Option<String>,
+    // RFC 9421 Signature-Input header; None for draft-cavage signatures
+    pub signature_input: Option<String>,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureFormat {
+    // draft-cavage-http-signatures: Signature with keyId/headers/signature
+    #[default]
+    Cavage,
+    // RFC 9421 HTTP Message Signatures: Signature-Input and Signature
+    Rfc9421,
+}
+
+// Applies to the current account only
+pub fn set_signature_format(host: String, format: SignatureFormat) {
+    if let Ok(mut formats) = current_account().signature_formats.lock() {
+        formats.insert(host.to_lowercase(), format);
+    }
+}
+
+pub fn get_signature_format(host: &str) -> SignatureFormat {
+    current_account()
+        .signature_formats
+        .lock()
+        .ok()
+        .and_then(|x| x.get(&host.to_lowercase()).copied())
+        .unwrap_or_default()
+}
+
+// A server advertises RFC 9421 support by sending Accept-Signature (RFC 9421
+// section 5.1); once seen, requests to that host are signed that way
+pub fn note_signature_capability(host: &str, response: &HttpResponse) {
+    if response.header("Accept-Signature").is_some()
+        && get_signature_format(host) != SignatureFormat::Rfc9421
+    {
+        set_signature_format(host.to_string(), SignatureFormat::Rfc9421);
+    }
 }
 
-fn compute_hash(params: &SignParams) -> Option<String> {
+fn compute_digest(params: &SignParams) -> Option<String> {
     let mut hasher = Sha256::new();
 
     match params {
@@ -68,119 +258,361 @@ fn compute_hash(params: &SignParams) -> Option<String> {
         }
         _ => return None,
     }
-    let hashed = general_purpose::STANDARD.encode(hasher.finalize());
-    Some(format!("sha-256={hashed}"))
+
+    Some(general_purpose::STANDARD.encode(hasher.finalize()))
 }
 
 fn format_http_date_now() -> String {
     // This seems unnecessarily complex, but the complexity is necessary
-    // because it is relying on a browser function (date_now) exported in lib.rs
+    // because it is relying on a browser function (date_now) exported in lib.rs;
+    // server_now corrects it by the offset measured from the server's Date
     fn perf_to_system(amt: f64) -> std::time::SystemTime {
         let secs = (amt as u64) / 1_000;
         let nanos = ((amt as u32) % 1_000) * 1_000_000;
         std::time::UNIX_EPOCH + std::time::Duration::new(secs, nanos)
     }
 
-    httpdate::fmt_http_date(perf_to_system(date_now()))
+    httpdate::fmt_http_date(perf_to_system(server_now()))
 }
 
-fn create_signature(
-    signing_key: &SigningKey<Sha256>,
-    request_target: &str,
-    host: &str,
+fn cavage_signature(
+    signing_key: &ClientSigningKey,
+    key_id: &str,
+    params: &SignParams,
     date: &str,
-    hash: &Option<String>,
-) -> Signature {
-    let signed_string = if let Some(hash) = hash {
-        format!("(request-target): {request_target}\nhost: {host}\ndate: {date}\ndigest: {hash}")
+    digest: &Option<String>,
+) -> String {
+    let request_target = format!(
+        "{} {}",
+        params.method.to_string().to_lowercase(),
+        params.request_target
+    );
+    let host = &params.host;
+
+    let (signed_string, headers) = if let Some(digest) = digest {
+        (
+            format!(
+                "(request-target): {request_target}\nhost: {host}\ndate: {date}\ndigest: {digest}"
+            ),
+            "(request-target) host date digest",
+        )
     } else {
-        format!("(request-target): {request_target}\nhost: {host}\ndate: {date}")
+        (
+            format!("(request-target): {request_target}\nhost: {host}\ndate: {date}"),
+            "(request-target) host date",
+        )
     };
 
-    let mut rng = rand::thread_rng();
-    signing_key.sign_with_rng(&mut rng, signed_string.as_bytes())
+    let signature = signing_key.sign(&signed_string);
+
+    match signing_key.algorithm() {
+        KeyAlgorithm::Rsa => {
+            format!("keyId=\"{key_id}\",headers=\"{headers}\",signature=\"{signature}\"")
+        }
+        // verifiers assume rsa-sha256 when no algorithm is given; hs2019 tells
+        // them to use the algorithm of the published key instead
+        KeyAlgorithm::Ed25519 => format!(
+            "keyId=\"{key_id}\",algorithm=\"hs2019\",headers=\"{headers}\",signature=\"{signature}\""
+        ),
+    }
 }
 
-fn build_sign_response(
-    signature: Signature,
-    state: &EnigmatickState,
-    profile: &Profile,
-    date: &str,
-    hash: Option<String>,
-) -> SignResponse {
-    let signature_base64 = general_purpose::STANDARD.encode(signature.to_bytes());
-    let key_id = format!(
+// Returns the Signature-Input and Signature header values
+fn rfc9421_signature(
+    signing_key: &ClientSigningKey,
+    key_id: &str,
+    params: &SignParams,
+    content_digest: &Option<String>,
+) -> (String, String) {
+    let mut components = vec![
+        ("@method", params.method.to_string().to_uppercase()),
+        ("@path", params.request_target.clone()),
+        ("@authority", params.host.to_lowercase()),
+    ];
+
+    if let Some(content_digest) = content_digest {
+        components.push(("content-digest", content_digest.clone()));
+    }
+
+    let alg = match signing_key.algorithm() {
+        KeyAlgorithm::Rsa => "rsa-v1_5-sha256",
+        KeyAlgorithm::Ed25519 => "ed25519",
+    };
+
+    let created = (server_now() / 1_000.0) as u64;
+    let signature_params = format!(
+        "({});created={created};keyid=\"{key_id}\";alg=\"{alg}\"",
+        components
+            .iter()
+            .map(|(name, _)| format!("\"{name}\""))
+            .collect::<Vec<String>>()
+            .join(" ")
+    );
+
+    let mut signature_base = components
+        .iter()
+        .map(|(name, value)| format!("\"{name}\": {value}"))
+        .collect::<Vec<String>>();
+    signature_base.push(format!("\"@signature-params\": {signature_params}"));
+
+    let signature = signing_key.sign(&signature_base.join("\n"));
+
+    (
+        format!("sig1={signature_params}"),
+        format!("sig1=:{signature}:"),
+    )
+}
+
+// Accounts that have never rotated their key use the original #client-key
+pub fn client_key_id(state: &EnigmatickState, profile: &Profile) -> EnigmatickResult<String> {
+    if let Some(key_id) = profile.client_key_id.clone() {
+        return Ok(key_id);
+    }
+
+    Ok(format!(
         "{}/user/{}#client-key",
-        state.server_url.clone().unwrap(),
+        state
+            .server_url
+            .clone()
+            .ok_or(EnigmatickError::missing("server_url"))?,
         profile.username
-    );
-    let headers = if hash.is_some() {
-        "\"(request-target) host date digest\""
-    } else {
-        "\"(request-target) host date\""
-    };
+    ))
+}
+
+// how long (in seconds) a rotated-out client key remains valid by default
+pub const DEFAULT_KEY_GRACE_PERIOD: u32 = 60 * 60;
 
-    SignResponse {
-        signature: format!("keyId=\"{key_id}\",headers={headers},signature=\"{signature_base64}\""),
-        date: date.to_string(),
-        digest: hash,
+// Publishes key as the client key under a new keyId and switches state over
+// to it. The request is signed with the current key; the server keeps that
+// key's keyId valid for grace_period seconds so that requests already signed
+// with it still verify.
+pub async fn publish_client_key(
+    state: &EnigmatickState,
+    profile: &Profile,
+    key: ClientKeyPem,
+    grace_period: u32,
+) -> EnigmatickResult<Profile> {
+    #[derive(Serialize)]
+    struct ClientKeyRotation {
+        key_id: String,
+        previous_key_id: String,
+        client_public_key: String,
+        client_private_key: String,
+        grace_period: u32,
     }
+
+    let rotation = ClientKeyRotation {
+        key_id: format!(
+            "{}#client-key-{}",
+            profile.id,
+            (server_now() / 1_000.0) as u64
+        ),
+        previous_key_id: client_key_id(state, profile)?,
+        client_public_key: key.public_key,
+        client_private_key: encrypt(None, key.private_key.clone())?,
+        grace_period,
+    };
+
+    let url = format!("/api/user/{}/client-key", profile.username);
+    let response = send_post(
+        url,
+        serde_json::to_string(&rotation)?,
+        "application/json".to_string(),
+    )
+    .await?;
+    let user: Profile = serde_json::from_str(&response)?;
+
+    // requests from here on are signed with the new key (the cached signing
+    // key is replaced when the PEM changes)
+    update_state(|state| {
+        state.set_profile(user.clone());
+        state.set_client_private_key_pem(key.private_key);
+        Ok(())
+    })?;
+
+    Ok(user)
 }
 
-pub fn sign(params: SignParams) -> Option<SignResponse> {
-    let hash = compute_hash(&params);
-    let request_target = format!(
-        "{} {}",
-        params.method.to_string().to_lowercase(),
-        params.request_target
-    );
+pub fn sign(params: SignParams) -> EnigmatickResult<SignResponse> {
+    let digest = compute_digest(&params);
     let date = format_http_date_now();
 
     let state = get_state();
-    if let (Some(private_key_pem), Some(profile)) = (&state.client_private_key_pem, &state.profile)
-    {
-        let private_key = RsaPrivateKey::from_pkcs8_pem(private_key_pem).unwrap();
-        let signing_key = SigningKey::<Sha256>::new(private_key);
-        let signature = create_signature(&signing_key, &request_target, &params.host, &date, &hash);
+    let profile = state
+        .profile
+        .as_ref()
+        .ok_or(EnigmatickError::missing("profile"))?;
+
+    let signing_key =
+        with_secrets(|secrets| client_signing_key(secrets.client_private_key_pem()?.expose()))?;
+    let key_id = client_key_id(&state, profile)?;
+
+    // both digest headers are sent so that either kind of verifier can check
+    // the body, regardless of which signature format covers it
+    let mut response = SignResponse {
+        date: date.clone(),
+        digest: digest.as_ref().map(|x| format!("sha-256={x}")),
+        content_digest: digest.as_ref().map(|x| format!("sha-256=:{x}:")),
+        ..Default::default()
+    };
 
-        return Some(build_sign_response(signature, &state, profile, &date, hash));
+    match get_signature_format(&params.host) {
+        SignatureFormat::Cavage => {
+            response.signature =
+                cavage_signature(&signing_key, &key_id, &params, &date, &response.digest);
+        }
+        SignatureFormat::Rfc9421 => {
+            let (signature_input, signature) =
+                rfc9421_signature(&signing_key, &key_id, &params, &response.content_digest);
+            response.signature_input = Some(signature_input);
+            response.signature = signature;
+        }
     }
 
-    None
+    Ok(response)
 }
 
 pub fn encode_derived_key(derived_key: &SecretKey) -> String {
     general_purpose::STANDARD.encode(derived_key.unprotected_as_bytes())
 }
 
-pub fn derive_key(password_str: String, encoded_salt: String) -> Result<SecretKey, Box<dyn Error>> {
-    let salt = kdf::Salt::from_slice(&general_purpose::STANDARD.decode(encoded_salt)?)?;
-    let password = kdf::Password::from_slice(password_str.as_bytes())?;
+// Argon2i parameters for the key that protects the client key, olm account
+// and MLS instruments. They are stored next to the salt in the Profile so that
+// the defaults can be raised without locking anyone out.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct KdfParams {
+    pub version: u32,
+    pub iterations: u32,
+    pub memory_kib: u32,
+    // the parameters in use before a migration; data sealed under the old key
+    // and not re-encrypted (e.g., vault entries) is still opened with it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<Box<KdfParams>>,
+}
+
+pub const KDF_VERSION: u32 = 2;
+
+pub const DEFAULT_KDF_ITERATIONS: u32 = 3;
+pub const DEFAULT_KDF_MEMORY_KIB: u32 = 1 << 16;
+
+impl KdfParams {
+    // what every account used before parameters were stored in the Profile
+    pub fn legacy() -> Self {
+        KdfParams {
+            version: 1,
+            iterations: 3,
+            memory_kib: 1 << 4,
+            previous: None,
+        }
+    }
+
+    // the parameters for new keys (set_kdf_params)
+    pub fn current() -> Self {
+        let account = current_account();
+
+        KdfParams {
+            version: KDF_VERSION,
+            iterations: account.kdf_iterations.load(Ordering::SeqCst),
+            memory_kib: account.kdf_memory_kib.load(Ordering::SeqCst),
+            previous: None,
+        }
+    }
+
+    pub fn of(profile: &Profile) -> Self {
+        profile.kdf.clone().unwrap_or_else(KdfParams::legacy)
+    }
+
+    pub fn is_outdated(&self) -> bool {
+        let current = KdfParams::current();
 
-    Ok(kdf::derive_key(&password, &salt, 3, 1 << 4, 32)?)
+        self.version < current.version
+            || self.iterations < current.iterations
+            || self.memory_kib < current.memory_kib
+    }
+
+    // The parameters to migrate to, remembering these as the previous ones
+    pub fn upgrade(&self) -> Self {
+        KdfParams {
+            previous: Some(Box::new(self.clone())),
+            ..KdfParams::current()
+        }
+    }
 }
 
-#[wasm_bindgen]
-pub fn decrypt_text(encoded_data: String) -> String {
-    decrypt(None, encoded_data).unwrap_or_default()
+// Raises (or lowers, e.g. for constrained devices) the Argon2i cost used for
+// new keys of the current account (and of accounts signed in after it);
+// existing accounts on weaker parameters are migrated at login
+pub fn set_kdf_params(iterations: u32, memory_kib: u32) -> Result<(), EnigmatickError> {
+    // the minimums enforced by orion
+    if iterations < 3 || memory_kib < 8 {
+        return Err(EnigmatickError::InvalidInput(format!(
+            "KDF parameters too weak: {iterations} iterations, {memory_kib} KiB"
+        )));
+    }
+
+    let account = current_account();
+    account.kdf_iterations.store(iterations, Ordering::SeqCst);
+    account.kdf_memory_kib.store(memory_kib, Ordering::SeqCst);
+
+    Ok(())
 }
 
-pub fn decrypt(
-    derived_key: Option<String>,
-    encoded_data: String,
-) -> Result<String, Box<dyn Error>> {
-    let derived_key = derived_key.unwrap_or(
-        get_state()
-            .derived_key
-            .clone()
-            .ok_or("derived_key missing")?,
-    );
-    let decoded_key = general_purpose::STANDARD.decode(derived_key)?;
-    let secret_key = SecretKey::from_slice(&decoded_key)?;
-    let decrypted = aead::open(
-        &secret_key,
-        &general_purpose::STANDARD.decode(encoded_data).unwrap(),
-    )?;
+pub fn derive_key(
+    password_str: String,
+    encoded_salt: String,
+    params: &KdfParams,
+) -> EnigmatickResult<SecretKey> {
+    let salt = kdf::Salt::from_slice(&general_purpose::STANDARD.decode(encoded_salt)?)
+        .map_err(EnigmatickError::encryption)?;
+    let password =
+        kdf::Password::from_slice(password_str.as_bytes()).map_err(EnigmatickError::encryption)?;
+
+    kdf::derive_key(&password, &salt, params.iterations, params.memory_kib, 32)
+        .map_err(EnigmatickError::encryption)
+}
+
+// The encoded keys for every earlier set of parameters, newest first
+pub fn derive_previous_keys(
+    password_str: String,
+    encoded_salt: String,
+    params: &KdfParams,
+) -> EnigmatickResult<Vec<String>> {
+    let mut keys = vec![];
+    let mut previous = params.previous.as_deref();
+
+    while let Some(params) = previous {
+        keys.push(encode_derived_key(&derive_key(
+            password_str.clone(),
+            encoded_salt.clone(),
+            params,
+        )?));
+        previous = params.previous.as_deref();
+    }
+
+    Ok(keys)
+}
+
+pub fn decrypt_text(encoded_data: String) -> Result<String, EnigmatickError> {
+    decrypt(None, encoded_data)
+}
+
+fn decode_secret_key(encoded_key: &str) -> EnigmatickResult<SecretKey> {
+    let decoded_key = Zeroizing::new(general_purpose::STANDARD.decode(encoded_key)?);
+
+    SecretKey::from_slice(&decoded_key).map_err(EnigmatickError::decryption)
+}
+
+// An explicit key is wiped once decoded; otherwise the state's key is decoded
+// in place
+pub(crate) fn secret_key(derived_key: Option<String>) -> EnigmatickResult<SecretKey> {
+    match derived_key {
+        Some(derived_key) => decode_secret_key(&Zeroizing::new(derived_key)),
+        None => with_secrets(|secrets| decode_secret_key(secrets.derived_key()?.expose())),
+    }
+}
+
+pub fn decrypt(derived_key: Option<String>, encoded_data: String) -> EnigmatickResult<String> {
+    let decrypted = decrypt_bytes(derived_key, encoded_data)?;
     let decrypted_str = String::from_utf8(decrypted)?;
 
     Ok(decrypted_str)
@@ -189,60 +621,42 @@ pub fn decrypt(
 pub fn decrypt_bytes(
     derived_key: Option<String>,
     encoded_data: String,
-) -> Result<Vec<u8>, Box<dyn Error>> {
-    let derived_key = derived_key.unwrap_or(
-        get_state()
-            .derived_key
-            .clone()
-            .ok_or("derived_key missing")?,
-    );
-    let decoded_key = general_purpose::STANDARD.decode(derived_key)?;
-    let secret_key = SecretKey::from_slice(&decoded_key)?;
-    let decrypted = aead::open(
-        &secret_key,
-        &general_purpose::STANDARD.decode(encoded_data).unwrap(),
-    )?;
-
-    Ok(decrypted)
+) -> EnigmatickResult<Vec<u8>> {
+    let explicit = derived_key.is_some();
+    let data = general_purpose::STANDARD.decode(encoded_data)?;
+
+    match aead::open(&secret_key(derived_key)?, &data) {
+        Ok(decrypted) => Ok(decrypted),
+        // data sealed before a KDF migration is still under an earlier key
+        Err(e) if !explicit => with_secrets(|secrets| {
+            secrets
+                .previous_derived_keys()
+                .iter()
+                .find_map(|key| aead::open(&decode_secret_key(key.expose()).ok()?, &data).ok())
+                .ok_or(EnigmatickError::decryption(e))
+        }),
+        Err(e) => Err(EnigmatickError::decryption(e)),
+    }
 }
 
-pub fn encrypt(derived_key: Option<String>, data: String) -> Result<String> {
-    let derived_key = derived_key.unwrap_or(
-        get_state()
-            .derived_key
-            .clone()
-            .ok_or(anyhow!("derived_key missing"))?,
-    );
-    let decoded_key = general_purpose::STANDARD.decode(derived_key)?;
-    let secret_key = SecretKey::from_slice(&decoded_key)?;
-    let encrypted = aead::seal(&secret_key, data.as_bytes())?;
+pub fn encrypt(derived_key: Option<String>, data: String) -> EnigmatickResult<String> {
+    let encrypted = encrypt_bytes(derived_key, data.as_bytes())?;
 
     Ok(general_purpose::STANDARD.encode(encrypted))
 }
 
-pub fn encrypt_bytes(derived_key: Option<String>, data: &[u8]) -> Result<Vec<u8>> {
-    let derived_key = derived_key.unwrap_or(
-        get_state()
-            .derived_key
-            .clone()
-            .ok_or(anyhow!("derived_key missing"))?,
-    );
-    let decoded_key = general_purpose::STANDARD.decode(derived_key)?;
-    let secret_key = SecretKey::from_slice(&decoded_key)?;
-    let encrypted = aead::seal(&secret_key, data)?;
+pub fn encrypt_bytes(derived_key: Option<String>, data: &[u8]) -> EnigmatickResult<Vec<u8>> {
+    let secret_key = secret_key(derived_key)?;
 
-    Ok(encrypted)
+    aead::seal(&secret_key, data).map_err(EnigmatickError::encryption)
 }
 
-pub fn get_key() -> Result<Vec<u8>> {
-    let derived_key = get_state()
-        .derived_key
-        .clone()
-        .ok_or(anyhow!("derived_key missing"))?;
-
-    general_purpose::STANDARD
-        .decode(derived_key)
-        .map_err(anyhow::Error::msg)
+pub fn get_key() -> EnigmatickResult<Zeroizing<Vec<u8>>> {
+    with_secrets(|secrets| {
+        Ok(Zeroizing::new(
+            general_purpose::STANDARD.decode(secrets.derived_key()?.expose())?,
+        ))
+    })
 }
 
 // Add Send + Sync bounds
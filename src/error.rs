@@ -0,0 +1,180 @@
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+pub type EnigmatickResult<T> = std::result::Result<T, EnigmatickError>;
+
+// Errors surfaced by every export. JavaScript receives these as thrown Error
+// objects with `name` set to "EnigmatickError" and `code`/`status` properties
+// attached so that the UI can branch on the failure; Rust callers get a
+// std::error::Error that converts cleanly into anyhow::Error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EnigmatickError {
+    // there is no authenticated session (or no profile) in state
+    NotAuthenticated,
+
+    // something the call depends on has not been loaded into state yet
+    // (e.g., server_name before load_instance_information)
+    MissingState(String),
+
+    // the arguments passed in could not be used to build a request
+    InvalidInput(String),
+
+    // the request never produced an HTTP response
+    Network(String),
+
+    // the request did not complete within its timeout (in milliseconds)
+    Timeout(u64),
+
+    // the request was abandoned through a CancellationToken
+    Cancelled,
+
+    // the key material was wiped by lock() or the idle timeout; unlock()
+    // restores it
+    Locked,
+
+    // the server responded with a non-success status
+    Http { status: u16, message: String },
+
+    Encryption(String),
+    Decryption(String),
+
+    // the server (or a caller) provided data that could not be deserialized
+    Parse(String),
+
+    // an HTTP signature or body digest did not check out
+    Verification(String),
+
+    Internal(String),
+
+    // the activity could not be delivered now and was placed on the offline
+    // outbox queue; the value is the queue entry ID
+    Queued(String),
+}
+
+impl EnigmatickError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            EnigmatickError::NotAuthenticated => "NOT_AUTHENTICATED",
+            EnigmatickError::MissingState(_) => "MISSING_STATE",
+            EnigmatickError::InvalidInput(_) => "INVALID_INPUT",
+            EnigmatickError::Network(_) => "NETWORK",
+            EnigmatickError::Timeout(_) => "TIMEOUT",
+            EnigmatickError::Cancelled => "CANCELLED",
+            EnigmatickError::Locked => "LOCKED",
+            EnigmatickError::Http { .. } => "HTTP",
+            EnigmatickError::Encryption(_) => "ENCRYPTION_FAILED",
+            EnigmatickError::Decryption(_) => "DECRYPTION_FAILED",
+            EnigmatickError::Parse(_) => "PARSE",
+            EnigmatickError::Verification(_) => "VERIFICATION_FAILED",
+            EnigmatickError::Internal(_) => "INTERNAL",
+            EnigmatickError::Queued(_) => "QUEUED",
+        }
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            EnigmatickError::Http { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    // failures that may succeed if the same request is sent again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            EnigmatickError::Network(_) | EnigmatickError::Timeout(_) => true,
+            EnigmatickError::Http { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    pub fn missing(what: &str) -> Self {
+        EnigmatickError::MissingState(format!("{what} missing"))
+    }
+
+    pub fn encryption(e: impl fmt::Display) -> Self {
+        EnigmatickError::Encryption(e.to_string())
+    }
+
+    pub fn decryption(e: impl fmt::Display) -> Self {
+        EnigmatickError::Decryption(e.to_string())
+    }
+
+    pub fn parse(e: impl fmt::Display) -> Self {
+        EnigmatickError::Parse(e.to_string())
+    }
+}
+
+impl fmt::Display for EnigmatickError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnigmatickError::NotAuthenticated => write!(f, "not authenticated"),
+            EnigmatickError::MissingState(x) => write!(f, "{x}"),
+            EnigmatickError::InvalidInput(x) => write!(f, "invalid input: {x}"),
+            EnigmatickError::Network(x) => write!(f, "network error: {x}"),
+            EnigmatickError::Timeout(x) => write!(f, "request timed out after {x}ms"),
+            EnigmatickError::Cancelled => write!(f, "request cancelled"),
+            EnigmatickError::Locked => write!(f, "session is locked"),
+            EnigmatickError::Http { status, message } => write!(f, "HTTP {status}: {message}"),
+            EnigmatickError::Encryption(x) => write!(f, "encryption failed: {x}"),
+            EnigmatickError::Decryption(x) => write!(f, "decryption failed: {x}"),
+            EnigmatickError::Parse(x) => write!(f, "unable to parse response: {x}"),
+            EnigmatickError::Verification(x) => write!(f, "verification failed: {x}"),
+            EnigmatickError::Internal(x) => write!(f, "{x}"),
+            EnigmatickError::Queued(x) => write!(f, "queued for delivery ({x})"),
+        }
+    }
+}
+
+impl std::error::Error for EnigmatickError {}
+
+impl From<EnigmatickError> for JsValue {
+    fn from(e: EnigmatickError) -> Self {
+        let error = js_sys::Error::new(&e.to_string());
+        error.set_name("EnigmatickError");
+
+        let status = e.status().map(JsValue::from).unwrap_or(JsValue::NULL);
+        js_sys::Reflect::set(&error, &"code".into(), &e.code().into()).ok();
+        js_sys::Reflect::set(&error, &"status".into(), &status).ok();
+
+        error.into()
+    }
+}
+
+// anyhow is used internally for the MLS plumbing; if the anyhow::Error is
+// wrapping one of ours, unwrap it so that the original code is preserved
+impl From<anyhow::Error> for EnigmatickError {
+    fn from(e: anyhow::Error) -> Self {
+        e.downcast::<EnigmatickError>()
+            .unwrap_or_else(|e| EnigmatickError::Internal(e.to_string()))
+    }
+}
+
+impl From<serde_json::Error> for EnigmatickError {
+    fn from(e: serde_json::Error) -> Self {
+        EnigmatickError::parse(e)
+    }
+}
+
+impl From<base64::DecodeError> for EnigmatickError {
+    fn from(e: base64::DecodeError) -> Self {
+        EnigmatickError::parse(e)
+    }
+}
+
+// Errors passed through a Read or Write impl come back wrapped in io::Error
+impl From<std::io::Error> for EnigmatickError {
+    fn from(e: std::io::Error) -> Self {
+        let description = e.to_string();
+
+        match e.into_inner().map(|x| x.downcast::<EnigmatickError>()) {
+            Some(Ok(e)) => *e,
+            _ => EnigmatickError::Internal(description),
+        }
+    }
+}
+
+impl From<std::string::FromUtf8Error> for EnigmatickError {
+    fn from(e: std::string::FromUtf8Error) -> Self {
+        EnigmatickError::parse(e)
+    }
+}
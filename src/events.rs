@@ -0,0 +1,190 @@
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+// Typed state-change events, delivered synchronously once the state lock is released
+
+type Listener = Arc<dyn Fn(&EnigmatickEvent) + Send + Sync>;
+
+lazy_static! {
+    static ref LISTENERS: Mutex<Vec<(u32, Listener)>> = Mutex::new(vec![]);
+}
+
+static NEXT_SUBSCRIPTION: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    static DEFERRED: Cell<u32> = const { Cell::new(0) };
+    static PENDING: RefCell<Vec<EnigmatickEvent>> = const { RefCell::new(Vec::new()) };
+
+    // JavaScript functions cannot be shared across threads
+    #[cfg(target_arch = "wasm32")]
+    static JS_LISTENERS: RefCell<Vec<(u32, js_sys::Function)>> = const { RefCell::new(Vec::new()) };
+}
+
+// JavaScript receives these as objects with a "type" property (e.g.,
+// { type: "profile_updated", actor: "https://..." })
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EnigmatickEvent {
+    Authenticated {
+        actor: String,
+    },
+    LoggedOut {
+        actor: String,
+    },
+    ProfileUpdated {
+        actor: String,
+    },
+    // key material was put in place (at login, or by unlock)
+    Unlocked {
+        actor: Option<String>,
+    },
+    // key material was wiped by lock() or the idle timeout
+    Locked {
+        actor: Option<String>,
+    },
+    // MLS credentials or group state were stored on the server
+    MlsStorageMutated {
+        actor: String,
+    },
+    // an activity was accepted by the server; queue_id is set when it was
+    // delivered from the offline queue
+    OutboxDelivered {
+        activity_id: Option<String>,
+        queue_id: Option<String>,
+    },
+}
+
+pub fn subscribe<F>(listener: F) -> u32
+where
+    F: Fn(&EnigmatickEvent) + Send + Sync + 'static,
+{
+    let id = NEXT_SUBSCRIPTION.fetch_add(1, Ordering::SeqCst);
+
+    if let Ok(mut listeners) = LISTENERS.lock() {
+        listeners.push((id, Arc::new(listener)));
+    }
+
+    id
+}
+
+// callback is called with each event; returns the ID for unsubscribe_events
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn subscribe_events(callback: js_sys::Function) -> u32 {
+    let id = NEXT_SUBSCRIPTION.fetch_add(1, Ordering::SeqCst);
+    JS_LISTENERS.with(|x| x.borrow_mut().push((id, callback)));
+    id
+}
+
+#[wasm_bindgen]
+pub fn unsubscribe_events(id: u32) {
+    if let Ok(mut listeners) = LISTENERS.lock() {
+        listeners.retain(|(x, _)| *x != id);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    JS_LISTENERS.with(|x| x.borrow_mut().retain(|(x, _)| *x != id));
+}
+
+pub fn emit(event: EnigmatickEvent) {
+    PENDING.with(|x| x.borrow_mut().push(event));
+
+    if DEFERRED.with(Cell::get) == 0 {
+        flush();
+    }
+}
+
+// Raised for each activity accepted by the server, whether it was sent
+// directly or from the offline queue; a note to an MLS group carries the
+// updated group storage, which the server keeps as well
+pub(crate) fn activity_delivered(activity: &Value, queue_id: Option<String>) {
+    let text = |x: &Value| x.as_str().map(str::to_string);
+
+    emit(EnigmatickEvent::OutboxDelivered {
+        activity_id: activity.get("id").and_then(text),
+        queue_id,
+    });
+
+    // bare objects (e.g., a Note) carry their instruments at the top level
+    let instruments = match activity
+        .pointer("/object/instrument")
+        .or_else(|| activity.get("instrument"))
+    {
+        Some(Value::Array(x)) => x.iter().collect(),
+        Some(x) => vec![x],
+        None => vec![],
+    };
+
+    let mutates_storage = instruments.iter().any(|x| {
+        matches!(
+            x.get("type").and_then(Value::as_str),
+            Some("MlsStorage" | "mls_storage")
+        )
+    });
+
+    let actor = activity
+        .get("actor")
+        .or_else(|| activity.get("attributedTo"))
+        .and_then(text);
+
+    if let (true, Some(actor)) = (mutates_storage, actor) {
+        emit(EnigmatickEvent::MlsStorageMutated { actor });
+    }
+}
+
+// Holds events raised by f until it returns
+pub(crate) fn defer_events<T>(f: impl FnOnce() -> T) -> T {
+    DEFERRED.with(|x| x.set(x.get() + 1));
+    let result = f();
+    DEFERRED.with(|x| x.set(x.get() - 1));
+
+    if DEFERRED.with(Cell::get) == 0 {
+        flush();
+    }
+
+    result
+}
+
+fn flush() {
+    // listeners may raise events of their own; those are delivered in turn
+    loop {
+        let events = PENDING.with(|x| std::mem::take(&mut *x.borrow_mut()));
+
+        if events.is_empty() {
+            return;
+        }
+
+        events.iter().for_each(dispatch);
+    }
+}
+
+fn dispatch(event: &EnigmatickEvent) {
+    // cloned so that a listener can subscribe or unsubscribe
+    let listeners: Vec<Listener> = LISTENERS
+        .lock()
+        .map(|x| x.iter().map(|(_, listener)| listener.clone()).collect())
+        .unwrap_or_default();
+
+    for listener in listeners {
+        listener(event);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let Ok(value) = serde_wasm_bindgen::to_value(event) else {
+            return;
+        };
+
+        let callbacks: Vec<js_sys::Function> =
+            JS_LISTENERS.with(|x| x.borrow().iter().map(|(_, f)| f.clone()).collect());
+
+        for callback in callbacks {
+            callback.call1(&wasm_bindgen::JsValue::NULL, &value).ok();
+        }
+    }
+}
@@ -0,0 +1,567 @@
+use js_sys::Promise;
+use wasm_bindgen::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+use crate::EnigmatickCache;
+use crate::{
+    ArticleParams, CancellationToken, Client, EnigmatickError, EnigmatickState,
+    InstanceInformation, KeyAlgorithm, NoteParams, Profile, QuestionParams, SignatureFormat,
+};
+
+// The JavaScript API. Each export runs the Client method of the same name on
+// the default client (the active account; see account.rs). The client is
+// resolved when the export starts, so switching accounts does not move work
+// that is already in flight to the new account. Exports that do not act on an
+// account (e.g., list_accounts and get_hash) are kept in their modules.
+
+#[wasm_bindgen]
+pub async fn load_instance_information(
+    url: Option<String>,
+) -> Result<InstanceInformation, EnigmatickError> {
+    Client::active().load_instance_information(url).await
+}
+
+#[wasm_bindgen]
+pub async fn authenticate(
+    username: String,
+    password_str: String,
+) -> Result<Profile, EnigmatickError> {
+    Client::active().authenticate(username, password_str).await
+}
+
+#[wasm_bindgen]
+pub async fn authenticate_legacy(
+    username: String,
+    password_str: String,
+) -> Result<Profile, EnigmatickError> {
+    Client::active()
+        .authenticate_legacy(username, password_str)
+        .await
+}
+
+#[wasm_bindgen]
+pub async fn logout() -> Result<(), EnigmatickError> {
+    Client::active().logout().await
+}
+
+#[wasm_bindgen]
+pub async fn create_user(
+    username: String,
+    display_name: String,
+    password_str: String,
+    key_algorithm: Option<KeyAlgorithm>,
+    recovery_phrase: Option<String>,
+) -> Result<Profile, EnigmatickError> {
+    Client::active()
+        .create_user(
+            username,
+            display_name,
+            password_str,
+            key_algorithm,
+            recovery_phrase,
+        )
+        .await
+}
+
+#[wasm_bindgen]
+pub async fn upload_image(data: &[u8]) -> Result<String, EnigmatickError> {
+    Client::active().upload_image(data).await
+}
+
+#[wasm_bindgen]
+pub async fn upload_avatar(data: &[u8], extension: String) -> Result<(), EnigmatickError> {
+    Client::active().upload_avatar(data, extension).await
+}
+
+#[wasm_bindgen]
+pub async fn upload_banner(data: &[u8], extension: String) -> Result<(), EnigmatickError> {
+    Client::active().upload_banner(data, extension).await
+}
+
+#[wasm_bindgen]
+pub async fn update_password(
+    current_str: String,
+    updated_str: String,
+) -> Result<bool, EnigmatickError> {
+    Client::active()
+        .update_password(current_str, updated_str)
+        .await
+}
+
+#[wasm_bindgen]
+pub async fn rotate_client_key(
+    key_algorithm: Option<KeyAlgorithm>,
+    grace_period: Option<u32>,
+) -> Result<Profile, EnigmatickError> {
+    Client::active()
+        .rotate_client_key(key_algorithm, grace_period)
+        .await
+}
+
+#[wasm_bindgen]
+pub async fn update_summary(summary: String, markdown: String) -> Result<String, EnigmatickError> {
+    Client::active().update_summary(summary, markdown).await
+}
+
+#[wasm_bindgen]
+pub async fn get_ap_id() -> Result<String, EnigmatickError> {
+    Client::active().get_ap_id().await
+}
+
+#[wasm_bindgen]
+pub async fn get_webfinger() -> Result<String, EnigmatickError> {
+    Client::active().get_webfinger().await
+}
+
+#[wasm_bindgen]
+pub async fn get_followers(username: String, page: Option<u32>) -> Result<String, EnigmatickError> {
+    Client::active().get_followers(username, page).await
+}
+
+#[wasm_bindgen]
+pub async fn get_following(username: String, page: Option<u32>) -> Result<String, EnigmatickError> {
+    Client::active().get_following(username, page).await
+}
+
+#[wasm_bindgen]
+pub async fn get_profile_by_username(username: String) -> Result<String, EnigmatickError> {
+    Client::active().get_profile_by_username(username).await
+}
+
+#[wasm_bindgen]
+pub fn is_locked() -> bool {
+    Client::active().is_locked()
+}
+
+#[wasm_bindgen]
+pub fn lock() {
+    Client::active().lock()
+}
+
+#[wasm_bindgen]
+pub fn note_activity() {
+    Client::active().note_activity()
+}
+
+#[wasm_bindgen]
+pub fn set_idle_timeout(seconds: u32) {
+    Client::active().set_idle_timeout(seconds)
+}
+
+#[wasm_bindgen]
+pub fn unlock(password: String) -> Result<(), EnigmatickError> {
+    Client::active().unlock(password)
+}
+
+#[wasm_bindgen]
+pub fn get_state() -> EnigmatickState {
+    Client::active().state()
+}
+
+#[wasm_bindgen]
+pub async fn persist_state() -> Result<(), EnigmatickError> {
+    Client::active().persist_state().await
+}
+
+#[wasm_bindgen]
+pub async fn persisted_username() -> Result<Option<String>, EnigmatickError> {
+    Client::active().persisted_username().await
+}
+
+#[wasm_bindgen]
+pub async fn restore_state(password: String) -> Result<bool, EnigmatickError> {
+    Client::active().restore_state(password).await
+}
+
+#[wasm_bindgen]
+pub async fn clear_persisted_state() -> Result<(), EnigmatickError> {
+    Client::active().clear_persisted_state().await
+}
+
+#[wasm_bindgen]
+pub async fn add_recovery_phrase(phrase: String) -> Result<(), EnigmatickError> {
+    Client::active().add_recovery_phrase(phrase).await
+}
+
+#[wasm_bindgen]
+pub async fn recover_account(
+    username: String,
+    phrase: String,
+    password_str: String,
+) -> Result<Profile, EnigmatickError> {
+    Client::active()
+        .recover_account(username, phrase, password_str)
+        .await
+}
+
+#[wasm_bindgen]
+pub async fn export_backup(passphrase: String) -> Result<String, EnigmatickError> {
+    Client::active().export_backup(passphrase).await
+}
+
+#[wasm_bindgen]
+pub async fn import_backup(
+    backup: String,
+    passphrase: String,
+    allow_other_actor: Option<bool>,
+) -> Result<(), EnigmatickError> {
+    Client::active()
+        .import_backup(backup, passphrase, allow_other_actor)
+        .await
+}
+
+#[wasm_bindgen]
+pub async fn get_timeline(
+    max: Option<String>,
+    min: Option<String>,
+    limit: i32,
+    view: String,
+    hashtags: JsValue,
+) -> Result<String, EnigmatickError> {
+    // undefined or null is no filter; anything else has to be a list of strings
+    let hashtags: Vec<String> = serde_wasm_bindgen::from_value::<Option<Vec<String>>>(hashtags)
+        .map_err(|e| EnigmatickError::InvalidInput(format!("invalid hashtags: {e}")))?
+        .unwrap_or_default();
+
+    Client::active()
+        .get_timeline(max, min, limit, view, hashtags)
+        .await
+}
+#[wasm_bindgen]
+pub async fn get_conversation(conversation: String, limit: i32) -> Result<String, EnigmatickError> {
+    Client::active().get_conversation(conversation, limit).await
+}
+
+#[wasm_bindgen]
+pub async fn get_inbox(offset: i32, limit: i32) -> Result<String, EnigmatickError> {
+    Client::active().get_inbox(offset, limit).await
+}
+
+#[wasm_bindgen]
+pub async fn get_outbox(
+    username: String,
+    kind: Option<String>,
+    timestamp: Option<String>,
+) -> Result<String, EnigmatickError> {
+    Client::active().get_outbox(username, kind, timestamp).await
+}
+
+#[wasm_bindgen]
+pub async fn get_remote_resource(
+    resource: String,
+    webfinger: String,
+    page: Option<String>,
+) -> Result<String, EnigmatickError> {
+    Client::active()
+        .get_remote_resource(resource, webfinger, page)
+        .await
+}
+
+#[wasm_bindgen]
+pub async fn get_remote_following(
+    webfinger: String,
+    page: Option<String>,
+) -> Result<String, EnigmatickError> {
+    Client::active().get_remote_following(webfinger, page).await
+}
+
+#[wasm_bindgen]
+pub async fn get_remote_followers(
+    webfinger: String,
+    page: Option<String>,
+) -> Result<String, EnigmatickError> {
+    Client::active().get_remote_followers(webfinger, page).await
+}
+
+#[wasm_bindgen]
+pub async fn get_remote_outbox(
+    webfinger: String,
+    page: Option<String>,
+) -> Result<String, EnigmatickError> {
+    Client::active().get_remote_outbox(webfinger, page).await
+}
+
+#[wasm_bindgen]
+pub async fn get_actor(
+    id: String,
+    timeout_ms: Option<u32>,
+    cancellation: Option<CancellationToken>,
+) -> Result<String, EnigmatickError> {
+    Client::active()
+        .get_actor(id, timeout_ms, cancellation)
+        .await
+}
+
+#[wasm_bindgen]
+pub fn get_actor_from_webfinger_promise(
+    webfinger: String,
+    timeout_ms: Option<u32>,
+    cancellation: Option<CancellationToken>,
+) -> Promise {
+    Client::active().get_actor_from_webfinger_promise(webfinger, timeout_ms, cancellation)
+}
+
+// the cache belongs to JavaScript, so there is no Client method for this
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub async fn get_actor_cached(cache: &EnigmatickCache, id: String) -> Option<Promise> {
+    Client::active()
+        .run(crate::get_actor_cached(cache, id))
+        .await
+}
+#[wasm_bindgen]
+pub async fn get_webfinger_from_id(id: String) -> Result<String, EnigmatickError> {
+    Client::active().get_webfinger_from_id(id).await
+}
+
+#[wasm_bindgen]
+pub async fn get_webfinger_from_handle(handle: String) -> Result<String, EnigmatickError> {
+    Client::active().get_webfinger_from_handle(handle).await
+}
+
+#[wasm_bindgen]
+pub async fn get_local_conversation(uuid: String) -> Result<String, EnigmatickError> {
+    Client::active().get_local_conversation(uuid).await
+}
+
+#[wasm_bindgen]
+pub async fn get_note(id: String) -> Result<String, EnigmatickError> {
+    Client::active().get_note(id).await
+}
+
+#[wasm_bindgen]
+pub async fn send_note(params: &mut NoteParams) -> Result<String, EnigmatickError> {
+    Client::active().send_note(params).await
+}
+
+#[wasm_bindgen]
+pub async fn send_vote(
+    option_name: String,
+    question_id: String,
+    question_author: String,
+) -> Result<String, EnigmatickError> {
+    Client::active()
+        .send_vote(option_name, question_id, question_author)
+        .await
+}
+
+#[wasm_bindgen]
+pub async fn send_question(question_json: String) -> Result<String, EnigmatickError> {
+    Client::active().send_question(question_json).await
+}
+
+#[wasm_bindgen]
+pub async fn send_follow(address: String) -> Result<String, EnigmatickError> {
+    Client::active().send_follow(address).await
+}
+
+#[wasm_bindgen]
+pub async fn send_unfollow(address: String, id: String) -> Result<String, EnigmatickError> {
+    Client::active().send_unfollow(address, id).await
+}
+
+#[wasm_bindgen]
+pub async fn send_like(to: String, object: String) -> Result<String, EnigmatickError> {
+    Client::active().send_like(to, object).await
+}
+
+#[wasm_bindgen]
+pub async fn send_unlike(
+    to: String,
+    object: String,
+    id: String,
+) -> Result<String, EnigmatickError> {
+    Client::active().send_unlike(to, object, id).await
+}
+
+#[wasm_bindgen]
+pub async fn send_announce(object: String) -> Result<String, EnigmatickError> {
+    Client::active().send_announce(object).await
+}
+
+#[wasm_bindgen]
+pub async fn send_unannounce(object: String, id: String) -> Result<String, EnigmatickError> {
+    Client::active().send_unannounce(object, id).await
+}
+
+#[wasm_bindgen]
+pub async fn send_delete(object: String) -> Result<bool, EnigmatickError> {
+    Client::active().send_delete(object).await
+}
+
+#[wasm_bindgen]
+pub async fn send_update(
+    object_id: String,
+    updated_object_json: String,
+) -> Result<String, EnigmatickError> {
+    Client::active()
+        .send_update(object_id, updated_object_json)
+        .await
+}
+
+#[wasm_bindgen]
+pub async fn send_update_note(params: &mut NoteParams) -> Result<String, EnigmatickError> {
+    Client::active().send_update_note(params).await
+}
+
+#[wasm_bindgen]
+pub async fn send_update_article(params: &mut ArticleParams) -> Result<String, EnigmatickError> {
+    Client::active().send_update_article(params).await
+}
+
+#[wasm_bindgen]
+pub async fn send_update_question(params: &mut QuestionParams) -> Result<String, EnigmatickError> {
+    Client::active().send_update_question(params).await
+}
+
+#[wasm_bindgen]
+pub async fn send_chess_invite(opponent_id: String) -> Result<String, EnigmatickError> {
+    Client::active().send_chess_invite(opponent_id).await
+}
+
+#[wasm_bindgen]
+pub async fn send_chess_accept(game_id: String) -> Result<String, EnigmatickError> {
+    Client::active().send_chess_accept(game_id).await
+}
+
+#[wasm_bindgen]
+pub async fn send_chess_move(
+    game_id: String,
+    from: String,
+    to: String,
+    promotion: Option<String>,
+) -> Result<String, EnigmatickError> {
+    Client::active()
+        .send_chess_move(game_id, from, to, promotion)
+        .await
+}
+
+#[wasm_bindgen]
+pub async fn send_chess_resign(game_id: String) -> Result<String, EnigmatickError> {
+    Client::active().send_chess_resign(game_id).await
+}
+
+#[wasm_bindgen]
+pub async fn upload_encrypted_attachment(
+    data: &[u8],
+    media_type: String,
+    name: Option<String>,
+) -> Result<String, EnigmatickError> {
+    Client::active()
+        .upload_encrypted_attachment(data, media_type, name)
+        .await
+}
+
+#[wasm_bindgen]
+pub fn open_encrypted_message(message: String) -> Result<String, EnigmatickError> {
+    Client::active().open_encrypted_message(message)
+}
+
+#[wasm_bindgen]
+pub async fn decrypt_attachment(attachment: String) -> Result<Vec<u8>, EnigmatickError> {
+    Client::active().decrypt_attachment(attachment).await
+}
+
+#[wasm_bindgen]
+pub async fn store_to_vault(
+    data: String,
+    remote_actor: String,
+    resolves: String,
+    session_uuid: String,
+    session: String,
+    mutation_of: String,
+) -> Result<String, EnigmatickError> {
+    Client::active()
+        .store_to_vault(
+            data,
+            remote_actor,
+            resolves,
+            session_uuid,
+            session,
+            mutation_of,
+        )
+        .await
+}
+
+#[wasm_bindgen]
+pub async fn get_vault(offset: i32, limit: i32, actor: String) -> Result<String, EnigmatickError> {
+    Client::active().get_vault(offset, limit, actor).await
+}
+
+#[wasm_bindgen]
+pub async fn send_authorization(uuid: String) -> Result<bool, EnigmatickError> {
+    Client::active().send_authorization(uuid).await
+}
+
+#[wasm_bindgen]
+pub async fn replenish_mkp() -> Result<bool, EnigmatickError> {
+    Client::active().replenish_mkp().await
+}
+
+#[wasm_bindgen]
+pub async fn resolve_processed_item(id: String) -> Result<String, EnigmatickError> {
+    Client::active().resolve_processed_item(id).await
+}
+
+#[wasm_bindgen]
+pub async fn get_processing_queue() -> Result<String, EnigmatickError> {
+    Client::active().get_processing_queue().await
+}
+
+#[wasm_bindgen]
+pub async fn drain_outbox_queue() -> Result<u32, EnigmatickError> {
+    Client::active().drain_outbox_queue().await
+}
+
+#[wasm_bindgen]
+pub async fn get_outbox_queue() -> Result<String, EnigmatickError> {
+    Client::active().get_outbox_queue().await
+}
+
+#[wasm_bindgen]
+pub async fn reorder_outbox_queue(ids: Vec<String>) -> Result<(), EnigmatickError> {
+    Client::active().reorder_outbox_queue(ids).await
+}
+
+#[wasm_bindgen]
+pub async fn cancel_queued_activity(id: String) -> Result<bool, EnigmatickError> {
+    Client::active().cancel_queued_activity(id).await
+}
+
+#[wasm_bindgen]
+pub fn set_signature_format(host: String, format: SignatureFormat) {
+    Client::active().set_signature_format(host, format)
+}
+
+#[wasm_bindgen]
+pub fn set_kdf_params(iterations: u32, memory_kib: u32) -> Result<(), EnigmatickError> {
+    Client::active().set_kdf_params(iterations, memory_kib)
+}
+
+#[wasm_bindgen]
+pub fn decrypt_text(encoded_data: String) -> Result<String, EnigmatickError> {
+    Client::active().decrypt_text(encoded_data)
+}
+
+#[wasm_bindgen]
+pub fn get_clock_offset() -> f64 {
+    Client::active().get_clock_offset()
+}
+
+#[wasm_bindgen]
+pub async fn verify_signature(
+    method: String,
+    path: String,
+    headers: String,
+    body: Option<String>,
+    actor: Option<String>,
+) -> Result<String, EnigmatickError> {
+    Client::active()
+        .verify_signature(method, path, headers, body, actor)
+        .await
+}
+
+#[wasm_bindgen]
+pub fn get_activity_ap_id_from_uuid(uuid: String) -> Result<String, EnigmatickError> {
+    Client::active().get_activity_ap_id_from_uuid(uuid)
+}
@@ -1,31 +1,41 @@
 use jdt_activity_pub::{ApObject, Collectible};
-use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::{authenticated, get_string, EnigmatickState, Method, Profile, SignParams};
+use crate::{
+    authenticated, resolve_url, send_signed, EnigmatickError, EnigmatickState, HttpRequest, Method,
+    Profile, SignParams,
+};
 
-#[wasm_bindgen]
-pub async fn get_inbox(offset: i32, limit: i32) -> Option<String> {
+pub async fn get_inbox(offset: i32, limit: i32) -> Result<String, EnigmatickError> {
     authenticated(move |state: EnigmatickState, profile: Profile| async move {
         let username = profile.username.clone();
 
         let inbox = format!("/user/{username}/inbox?offset={offset}&limit={limit}");
 
-        let signature = crate::crypto::sign(SignParams {
-            host: state.server_name.unwrap(),
-            request_target: inbox.clone(),
-            body: None,
-            data: None,
-            method: Method::Get,
-        })?;
+        let host = state
+            .server_name
+            .ok_or(EnigmatickError::missing("server_name"))?;
 
-        let response = get_string(inbox, Some(signature), "application/activity+json")
-            .await
-            .ok()??;
+        let response = send_signed(|| {
+            let signature = crate::crypto::sign(SignParams {
+                host: host.clone(),
+                request_target: inbox.clone(),
+                body: None,
+                data: None,
+                method: Method::Get,
+            })?;
 
-        if let ApObject::Collection(object) = serde_json::from_str(&response).ok()? {
-            Some(serde_json::to_string(&object.items()?).unwrap())
+            Ok(HttpRequest::new(Method::Get, resolve_url(&inbox))
+                .signature(Some(signature))
+                .header("Content-Type", "application/activity+json"))
+        })
+        .await?
+        .error_for_status()?
+        .text()?;
+
+        if let ApObject::Collection(object) = serde_json::from_str(&response)? {
+            Ok(serde_json::to_string(&object.items().unwrap_or_default())?)
         } else {
-            None
+            Err(EnigmatickError::parse("inbox response is not a Collection"))
         }
     })
     .await
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::{get_object, update_state};
+use crate::{get_object, update_state, EnigmatickError};
 
 #[wasm_bindgen(getter_with_clone)]
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -30,18 +30,18 @@ pub struct InstanceInformation {
     pub contact: ContactInformation,
 }
 
-#[wasm_bindgen]
-pub async fn load_instance_information(url: Option<String>) -> Option<InstanceInformation> {
+pub async fn load_instance_information(
+    url: Option<String>,
+) -> Result<InstanceInformation, EnigmatickError> {
     let url = format!("{}/api/v2/instance", url.unwrap_or_default());
 
-    let instance: InstanceInformation = get_object(url, None, "application/json").await.ok()?;
+    let instance: InstanceInformation = get_object(url, None, "application/json").await?;
 
     update_state(|state| {
         state.set_server_name(instance.domain.clone());
         state.set_server_url(instance.url.clone());
         Ok(())
-    })
-    .ok();
+    })?;
 
-    Some(instance)
+    Ok(instance)
 }
@@ -1,9 +1,7 @@
 #![allow(non_upper_case_globals)]
 
-use anyhow::Result;
 use base64::{engine::general_purpose, engine::Engine as _};
 use futures::Future;
-use jdt_activity_pub::ApAttachment;
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::de::DeserializeOwned;
@@ -13,56 +11,91 @@ use std::{
     cmp::Ordering,
     fmt::{self, Debug},
 };
+use uuid::Uuid;
 use wasm_bindgen::prelude::*;
 
-#[cfg(target_arch = "wasm32")]
-use gloo_net::http::Request;
-
+pub mod account;
 pub mod actor;
 pub mod announce;
+pub mod attachment;
+pub mod backup;
+pub mod cancel;
 pub mod chess;
+pub mod client;
+pub mod clock;
 pub mod crypto;
 pub mod delete;
+pub mod error;
+pub mod events;
+pub mod exports;
 pub mod follow;
 pub mod inbox;
 pub mod instance;
 pub mod keystore;
 pub mod like;
+pub mod lock;
 pub mod mls;
 pub mod note;
 pub mod outbox;
+pub mod outbox_queue;
 pub mod processing_queue;
+pub mod recovery;
+pub mod retry;
+pub mod sealed_stream;
 pub mod session;
+pub mod srp;
 pub mod state;
+pub mod state_store;
+pub mod storage;
 pub mod stream;
 pub mod timeline;
+pub mod transport;
 pub mod update;
 pub mod user;
 pub mod vault;
+pub mod verify;
 
 #[cfg(target_arch = "wasm32")]
 pub mod cache;
 
+pub use account::*;
 pub use actor::*;
 pub use announce::*;
+pub use attachment::*;
+pub use backup::*;
+pub use cancel::*;
 pub use chess::*;
+pub use client::*;
+pub use clock::*;
 pub use crypto::*;
 pub use delete::*;
+pub use error::*;
+pub use events::*;
 pub use follow::*;
 pub use inbox::*;
 pub use instance::*;
 pub use keystore::*;
 pub use like::*;
+pub use lock::*;
 pub use note::*;
 pub use outbox::*;
+pub use outbox_queue::*;
 pub use processing_queue::*;
+pub use recovery::*;
+pub use retry::*;
+pub use sealed_stream::*;
 pub use session::*;
+pub use srp::*;
 pub use state::*;
+pub use state_store::*;
+pub use storage::*;
 pub use stream::*;
 pub use timeline::*;
+pub use transport::*;
 pub use update::*;
 pub use user::*;
 pub use vault::*;
+pub use verify::*;
 
 #[cfg(target_arch = "wasm32")]
 pub use cache::*;
@@ -110,18 +143,23 @@ impl Ord for OrdValue {
     }
 }
 
-pub async fn authenticated<F, Fut>(f: F) -> Option<String>
+pub async fn authenticated<F, Fut, T>(f: F) -> EnigmatickResult<T>
 where
     F: FnOnce(EnigmatickState, Profile) -> Fut,
-    Fut: Future<Output = Option<String>>,
+    Fut: Future<Output = EnigmatickResult<T>>,
 {
     let state = get_state();
-    let profile = state.profile.clone()?;
+    let profile = state
+        .profile
+        .clone()
+        .ok_or(EnigmatickError::NotAuthenticated)?;
 
+    // the account stays the same for the whole call, even if another one is
+    // made active while it is waiting on the network
     if state.is_authenticated() {
-        f(state, profile.clone()).await
+        with_account(current_account(), f(state, profile.clone())).await
     } else {
-        None
+        Err(EnigmatickError::NotAuthenticated)
     }
 }
 
@@ -137,147 +175,75 @@ impl fmt::Display for Method {
     }
 }
 
-#[cfg(target_arch = "wasm32")]
-pub async fn get_object<T: DeserializeOwned>(
-    url: String,
-    signature: Option<SignResponse>,
-    content_type: &str,
-) -> Result<T> {
-    let mut request = Request::get(&url);
-
-    if let Some(signature) = signature {
-        request = request
-            .header("Enigmatick-Date", &signature.date)
-            .header("Signature", &signature.signature);
+// Endpoints are built as server-relative paths (e.g., /user/{username}/outbox); the
+// browser resolves those against the page origin, but reqwest needs an absolute URL
+// so paths are joined to the server_url pulled from /api/v2/instance when it is known
+pub fn resolve_url(url: &str) -> String {
+    if url.starts_with("https://") || url.starts_with("http://") {
+        return url.to_string();
     }
 
-    let resp = request.header("Content-Type", content_type).send().await?;
-    resp.json::<T>().await.map_err(anyhow::Error::msg)
+    match get_state().server_url {
+        Some(server_url) => format!(
+            "{}/{}",
+            server_url.trim_end_matches('/'),
+            url.trim_start_matches('/')
+        ),
+        None => url.to_string(),
+    }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-pub async fn get_object<T: DeserializeOwned>(
-    url: String,
-    signature: Option<SignResponse>,
-    content_type: &str,
-) -> Result<T> {
-    let client = reqwest::Client::new();
-    let mut client = client.get(&url);
+// Sends a request through the configured Transport, noting any signature
+// capabilities the server advertises and the server's clock
+pub async fn send_request(request: HttpRequest) -> EnigmatickResult<HttpResponse> {
+    let host = request.host().or_else(|| get_state().server_name);
+    let response = get_transport().send(request).await?;
 
-    if let Some(signature) = signature {
-        client = client.header("Enigmatick-Date", &signature.date);
-        client = client.header("Signature", &signature.signature);
+    note_server_date(&response);
+
+    if let Some(host) = host {
+        note_signature_capability(&host, &response);
     }
 
-    let resp = client.header("Content-Type", content_type).send().await?;
-    resp.json::<T>().await.map_err(anyhow::Error::msg)
+    Ok(response)
 }
 
-#[cfg(target_arch = "wasm32")]
-pub async fn get_string(
+pub async fn get_object<T: DeserializeOwned>(
     url: String,
     signature: Option<SignResponse>,
     content_type: &str,
-) -> Result<Option<String>> {
-    let mut request = Request::get(&url);
-
-    if let Some(signature) = signature {
-        request = request
-            .header("Enigmatick-Date", &signature.date)
-            .header("Signature", &signature.signature);
-    }
-
-    let response = request.header("Content-Type", content_type).send().await?;
+) -> EnigmatickResult<T> {
+    let request = HttpRequest::new(Method::Get, resolve_url(&url))
+        .signature(signature)
+        .header("Content-Type", content_type);
 
-    if response.ok() {
-        Ok(response.text().await.ok())
-    } else {
-        Ok(None)
-    }
+    send_request(request).await?.error_for_status()?.json::<T>()
 }
 
-#[cfg(not(target_arch = "wasm32"))]
 pub async fn get_string(
     url: String,
     signature: Option<SignResponse>,
     content_type: &str,
-) -> Result<Option<String>> {
-    let client = reqwest::Client::new();
-    let mut client = client.get(url);
-
-    if let Some(signature) = signature {
-        client = client
-            .header("Enigmatick-Date", &signature.date)
-            .header("Signature", &signature.signature);
-    }
-
-    let response = client.header("Content-Type", content_type).send().await?;
-
-    if response.status().is_success() {
-        Ok(response.text().await.ok())
-    } else {
-        Ok(None)
-    }
-}
-
-#[cfg(target_arch = "wasm32")]
-pub async fn post_string(
-    url: String,
-    body: String,
-    content_type: &str,
-    signature: Option<SignResponse>,
-) -> Option<String> {
-    let mut client = Request::post(&url);
-    if let Some(signature) = signature {
-        client = client
-            .header("Enigmatick-Date", &signature.date)
-            .header("Digest", &signature.digest.unwrap())
-            .header("Signature", &signature.signature);
-    }
+) -> EnigmatickResult<String> {
+    let request = HttpRequest::new(Method::Get, resolve_url(&url))
+        .signature(signature)
+        .header("Content-Type", content_type);
 
-    Some(
-        client
-            .header("Content-Type", &content_type)
-            .body(body)
-            .send()
-            .await
-            .ok()?
-            .text()
-            .await
-            .ok()?
-            .to_string(),
-    )
+    send_request(request).await?.error_for_status()?.text()
 }
 
-#[cfg(not(target_arch = "wasm32"))]
 pub async fn post_string(
     url: String,
     body: String,
     content_type: &str,
     signature: Option<SignResponse>,
-) -> Option<String> {
-    let client = reqwest::Client::new();
-    let mut client = client.post(url);
-
-    if let Some(signature) = signature {
-        client = client
-            .header("Enigmatick-Date", &signature.date)
-            .header("Digest", &signature.digest.unwrap())
-            .header("Signature", &signature.signature);
-    }
+) -> EnigmatickResult<String> {
+    let request = HttpRequest::new(Method::Post, resolve_url(&url))
+        .signature(signature)
+        .header("Content-Type", content_type)
+        .body(body.into_bytes());
 
-    Some(
-        client
-            .header("Content-Type", content_type)
-            .body(body.clone())
-            .send()
-            .await
-            .ok()?
-            .text()
-            .await
-            .ok()?
-            .to_string(),
-    )
+    send_request(request).await?.error_for_status()?.text()
 }
 
 pub async fn post_object<T: Serialize>(
@@ -285,87 +251,163 @@ pub async fn post_object<T: Serialize>(
     object: T,
     content_type: &str,
     signature: Option<SignResponse>,
-) -> Option<String> {
-    let body = serde_json::to_string(&object).ok()?;
+) -> EnigmatickResult<String> {
+    let body = serde_json::to_string(&object)?;
     post_string(url, body, content_type, signature).await
 }
 
-#[cfg(target_arch = "wasm32")]
 pub async fn post_bytes(
-    url: &String,
+    url: &str,
     bytes: &[u8],
-    length: u32,
-    content_type: &String,
+    content_type: &str,
     signature: Option<SignResponse>,
-) -> Option<String> {
-    let j_bytes = js_sys::Uint8Array::new_with_length(length);
-    j_bytes.copy_from(bytes);
+) -> EnigmatickResult<String> {
+    let request = HttpRequest::new(Method::Post, resolve_url(url))
+        .signature(signature)
+        .header("Content-Type", content_type)
+        .body(bytes.to_vec());
 
-    let mut client = Request::post(url);
+    send_request(request).await?.error_for_status()?.text()
+}
 
-    if let Some(signature) = signature {
-        client = client
-            .header("Enigmatick-Date", &signature.date)
-            .header("Digest", &signature.digest.unwrap())
-            .header("Signature", &signature.signature);
-    }
+fn sign_post(url: &str, body: &str) -> EnigmatickResult<SignResponse> {
+    let state = get_state();
 
-    client
-        .header("Content-Type", content_type)
-        .body(j_bytes)
-        .send()
-        .await
-        .ok()?
-        .text()
-        .await
-        .ok()
+    let url = url.split('?').collect::<Vec<&str>>()[0];
+
+    sign(SignParams {
+        host: state
+            .server_name
+            .ok_or(EnigmatickError::missing("server_name"))?,
+        request_target: url.to_string(),
+        body: Some(body.to_string()),
+        data: None,
+        method: Method::Post,
+    })
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-pub async fn post_bytes(
-    url: &String,
-    bytes: &[u8],
-    _length: u32,
-    content_type: &String,
-    signature: Option<SignResponse>,
-) -> Option<String> {
-    let client = reqwest::Client::new();
-    let mut client = client.post(url);
-
-    if let Some(signature) = signature {
-        client = client
-            .header("Enigmatick-Date", &signature.date)
-            .header("Digest", &signature.digest.unwrap())
-            .header("Signature", &signature.signature);
+pub async fn send_post(
+    url: String,
+    body: String,
+    content_type: String,
+) -> EnigmatickResult<String> {
+    send_signed(|| {
+        Ok(HttpRequest::new(Method::Post, resolve_url(&url))
+            .signature(Some(sign_post(&url, &body)?))
+            .header("Content-Type", &content_type)
+            .body(body.as_bytes().to_vec()))
+    })
+    .await?
+    .error_for_status()?
+    .text()
+}
+
+// Activities that do not already have an ID are given one on the server's
+// /activities/ path so that a retried POST can be recognized as a duplicate.
+// Bare objects (e.g., a Note or Question that the server wraps in a Create)
+// are given one on the /objects/ path instead, which the server's Create is
+// keyed by. Returns whether the payload now carries an ID.
+pub fn assign_activity_id(activity: &mut Value) -> EnigmatickResult<bool> {
+    let Some(object) = activity.as_object_mut() else {
+        return Ok(false);
+    };
+
+    if object.get("id").map_or(true, Value::is_null) {
+        let uuid = Uuid::new_v4().to_string();
+        let id = if object.contains_key("actor") {
+            get_activity_ap_id_from_uuid(uuid)?
+        } else {
+            get_object_ap_id_from_uuid(uuid)?
+        };
+        object.insert("id".to_string(), Value::String(id));
     }
 
-    client
-        .header("Content-Type", content_type)
-        .body(bytes.to_vec())
-        .send()
-        .await
-        .ok()?
-        .text()
-        .await
-        .ok()
+    Ok(true)
 }
 
-pub async fn send_post(url: String, body: String, content_type: String) -> Option<String> {
-    let signature = {
-        let state = get_state();
+// POSTs an ActivityPub payload to an outbox, retrying transient failures with
+// exponential backoff (or as directed by Retry-After). The request is re-signed
+// for every attempt so that the Date header is always current.
+pub async fn send_activity<T: Serialize>(outbox: String, activity: &T) -> EnigmatickResult<String> {
+    send_activity_with_policy(outbox, activity, RetryPolicy::default()).await
+}
 
-        let url = url.split('?').collect::<Vec<&str>>()[0];
+pub async fn send_activity_with_policy<T: Serialize>(
+    outbox: String,
+    activity: &T,
+    policy: RetryPolicy,
+) -> EnigmatickResult<String> {
+    let mut activity = serde_json::to_value(activity)?;
+    let idempotent = assign_activity_id(&mut activity)?;
+
+    // there is no point in waiting out a backoff when the browser already
+    // knows that it is offline
+    if !is_online() {
+        let id = queue_activity(outbox, activity, idempotent, None).await?;
+        return Err(EnigmatickError::Queued(id));
+    }
 
-        sign(SignParams {
-            host: state.server_name.clone()?,
-            request_target: url.to_string(),
-            body: Some(body.clone()),
-            data: None,
-            method: Method::Post,
-        })?
-    };
+    let body = serde_json::to_string(&activity)?;
 
-    post_string(url, body, &content_type, Some(signature)).await
+    match deliver_activity(&outbox, &body, idempotent, &policy).await {
+        Ok(response) => {
+            activity_delivered(&activity, None);
+            schedule_outbox_drain().await;
+            Ok(response)
+        }
+        Err(e) if e.is_transient() => {
+            let id = queue_activity(outbox, activity, idempotent, Some(e.to_string())).await?;
+            Err(EnigmatickError::Queued(id))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// The retry loop shared by send_activity and the offline queue; nothing is
+// queued from here
+pub async fn deliver_activity(
+    outbox: &str,
+    body: &str,
+    idempotent: bool,
+    policy: &RetryPolicy,
+) -> EnigmatickResult<String> {
+    let url = resolve_url(outbox);
+
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+
+        let request = || {
+            Ok(HttpRequest::new(Method::Post, url.clone())
+                .signature(Some(sign_post(outbox, body)?))
+                .header("Content-Type", "application/activity+json")
+                .body(body.as_bytes().to_vec()))
+        };
+
+        let (error, delay) = match send_signed(request).await {
+            Ok(response) => {
+                let delay = retry_after(&response);
+                match response.error_for_status() {
+                    Ok(response) => return response.text(),
+                    Err(e) => (e, delay),
+                }
+            }
+            Err(e) => (e, None),
+        };
+
+        if !policy.should_retry(&error, attempt, idempotent) {
+            return Err(error);
+        }
+
+        let delay = delay
+            .map(|x| x.min(policy.max_delay_ms))
+            .unwrap_or_else(|| policy.backoff(attempt));
+
+        log(&format!(
+            "POST to {outbox} failed ({error}); retrying in {delay}ms"
+        ));
+        sleep(delay).await;
+    }
 }
 
 pub async fn send_get_promise(
@@ -373,24 +415,7 @@ pub async fn send_get_promise(
     url: String,
     content_type: String,
 ) -> Result<JsValue, JsValue> {
-    let signature = {
-        let state = get_state();
-
-        let url = url.split('?').collect::<Vec<&str>>()[0];
-
-        sign(SignParams {
-            host: server_name.unwrap_or(state.server_name.unwrap()),
-            request_target: url.to_string(),
-            body: None,
-            data: None,
-            method: Method::Get,
-        })
-    };
-
-    let response = get_string(url, signature, &content_type)
-        .await
-        .map_err(|x| JsValue::from(x.to_string()))?
-        .ok_or(JsValue::UNDEFINED)?;
+    let response = send_get(server_name, url, content_type).await?;
 
     match response.as_str() {
         "" => Err(JsValue::UNDEFINED),
@@ -402,69 +427,92 @@ pub async fn send_get(
     server_name: Option<String>,
     url: String,
     content_type: String,
-) -> Option<String> {
-    let signature = {
+) -> EnigmatickResult<String> {
+    // GETs are signed when there is a client key available, but are still sent
+    // unsigned otherwise (e.g., before authentication); a locked session is
+    // reported as such rather than being sent unsigned and refused
+    let signature = || {
         let state = get_state();
 
         let url = url.split('?').collect::<Vec<&str>>()[0];
 
-        sign(SignParams {
-            host: server_name.unwrap_or(state.server_name?),
+        let Some(host) = server_name.clone().or(state.server_name) else {
+            return Ok(None);
+        };
+
+        match sign(SignParams {
+            host,
             request_target: url.to_string(),
             body: None,
             data: None,
             method: Method::Get,
-        })
+        }) {
+            Ok(signature) => Ok(Some(signature)),
+            Err(EnigmatickError::Locked) => Err(EnigmatickError::Locked),
+            Err(_) => Ok(None),
+        }
     };
 
-    get_string(url, signature, &content_type).await.ok()?
+    send_signed(|| {
+        Ok(HttpRequest::new(Method::Get, resolve_url(&url))
+            .signature(signature()?)
+            .header("Content-Type", &content_type))
+    })
+    .await?
+    .error_for_status()?
+    .text()
 }
 
 pub async fn upload_file(
     server_name: Option<String>,
     url: String,
     data: &[u8],
-    length: u32,
-) -> Option<String> {
-    let signature = {
+) -> EnigmatickResult<String> {
+    let signature = || {
         let state = get_state();
 
         let url = url.split('?').collect::<Vec<&str>>()[0];
         sign(SignParams {
-            host: server_name.unwrap_or(state.server_name?),
+            host: server_name
+                .clone()
+                .or(state.server_name)
+                .ok_or(EnigmatickError::missing("server_name"))?,
             request_target: url.to_string(),
             body: None,
             data: Some(Vec::from(data)),
             method: Method::Post,
-        })?
+        })
     };
 
-    if let Some(resp) = post_bytes(
-        &url,
-        data,
-        length,
-        &"application/octet-stream".to_string(),
-        Some(signature),
-    )
-    .await
-    {
-        if let Ok(attachment) = serde_json::from_str::<ApAttachment>(&resp) {
-            //log(&format!("upload completed\n{attachment:#?}"));
-            Some(serde_json::to_string(&attachment).unwrap())
-        } else {
-            None
-        }
-    } else {
-        None
-    }
+    send_signed(|| {
+        Ok(HttpRequest::new(Method::Post, resolve_url(&url))
+            .signature(Some(signature()?))
+            .header("Content-Type", "application/octet-stream")
+            .body(data.to_vec()))
+    })
+    .await?
+    .error_for_status()?
+    .text()
 }
 
-#[wasm_bindgen]
-pub fn get_activity_ap_id_from_uuid(uuid: String) -> Option<String> {
+pub fn get_activity_ap_id_from_uuid(uuid: String) -> Result<String, EnigmatickError> {
+    let state = get_state();
+    let server_name = state
+        .server_name
+        .clone()
+        .ok_or(EnigmatickError::missing("server_name"))?;
+
+    Ok(format!("https://{}/activities/{}", server_name, uuid))
+}
+
+pub fn get_object_ap_id_from_uuid(uuid: String) -> Result<String, EnigmatickError> {
     let state = get_state();
-    let server_name = state.server_name.clone()?;
+    let server_name = state
+        .server_name
+        .clone()
+        .ok_or(EnigmatickError::missing("server_name"))?;
 
-    Some(format!("https://{}/activities/{}", server_name, uuid))
+    Ok(format!("https://{}/objects/{}", server_name, uuid))
 }
 
 #[wasm_bindgen]
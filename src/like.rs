@@ -1,39 +1,31 @@
 use jdt_activity_pub::{ApAddress, ApLike, ApUndo};
 use jdt_activity_pub::MaybeMultiple;
-use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::{authenticated, log, send_post, EnigmatickState, Profile};
+use crate::{authenticated, log, send_activity, EnigmatickError, EnigmatickState, Profile};
 
-#[wasm_bindgen]
-pub async fn send_like(to: String, object: String) -> Option<String> {
-    authenticated(move |state: EnigmatickState, profile: Profile| async move {
+pub async fn send_like(to: String, object: String) -> Result<String, EnigmatickError> {
+    authenticated(move |_state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
 
         let like = ApLike::new(
-            state.profile?.id,
+            profile.id.clone(),
             MaybeMultiple::Single(ApAddress::from(to)),
             object.into(),
             None,
         );
 
         //log(&format!("LIKE\n{like:#?}"));
-        send_post(
-            outbox,
-            serde_json::to_string(&like).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &like).await
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn send_unlike(to: String, object: String, id: String) -> Option<String> {
-    authenticated(move |state: EnigmatickState, profile: Profile| async move {
+pub async fn send_unlike(to: String, object: String, id: String) -> Result<String, EnigmatickError> {
+    authenticated(move |_state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
 
         let like = ApLike::new(
-            state.profile?.id,
+            profile.id.clone(),
             MaybeMultiple::Single(ApAddress::from(to)),
             object.into(),
             Some(id),
@@ -41,12 +33,7 @@ pub async fn send_unlike(to: String, object: String, id: String) -> Option<Strin
         let undo: ApUndo = like.into();
 
         //log(&format!("UNLIKE\n{undo:#?}"));
-        send_post(
-            outbox,
-            serde_json::to_string(&undo).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &undo).await
     })
     .await
 }
@@ -0,0 +1,204 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::{
+    current_account, decrypt, derive_key, derive_previous_keys, emit, encode_derived_key,
+    get_state, local_now, update_state, Account, EnigmatickError, EnigmatickEvent,
+    EnigmatickResult, KdfParams, StateSecrets,
+};
+
+// Locking wipes the key material but keeps the profile, until unlock() puts it back
+
+#[derive(Debug, Default)]
+pub struct IdleTimer {
+    // 0 disables the idle timeout
+    timeout_ms: AtomicU64,
+    last_activity_ms: AtomicU64,
+    // incremented whenever the timeout changes so that an older watcher stops
+    generation: AtomicU64,
+}
+
+impl IdleTimer {
+    pub fn timeout_ms(&self) -> u64 {
+        self.timeout_ms.load(Ordering::SeqCst)
+    }
+
+    fn note_activity(&self) {
+        self.last_activity_ms
+            .store(local_now() as u64, Ordering::SeqCst);
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        match self.timeout_ms() {
+            0 => None,
+            timeout => Some(self.last_activity_ms.load(Ordering::SeqCst) + timeout),
+        }
+    }
+}
+
+pub fn is_locked() -> bool {
+    current_account().locked.load(Ordering::SeqCst)
+}
+
+// Dropping the old secrets wipes them
+fn lock_account(account: &Account) {
+    let was_locked = account.locked.swap(true, Ordering::SeqCst);
+
+    if let Ok(mut secrets) = account.secrets.lock() {
+        *secrets = StateSecrets::default();
+    }
+    account.forget_signing_key();
+
+    // this can run while the state is locked (from with_secrets inside
+    // update_state), so the actor is left out rather than waited for
+    if !was_locked {
+        emit(EnigmatickEvent::Locked {
+            actor: account.try_id(),
+        });
+    }
+}
+
+pub fn lock() {
+    lock_account(&current_account());
+}
+
+// Called when fresh key material is put in place
+pub fn mark_unlocked() {
+    current_account().locked.store(false, Ordering::SeqCst);
+    note_activity();
+}
+
+// Use of the secrets counts as activity; the UI should also call this on user
+// input so that reading without sending does not lock the session
+pub fn note_activity() {
+    current_account().idle.note_activity();
+}
+
+fn lock_account_if_idle(account: &Account) {
+    if account
+        .idle
+        .deadline()
+        .is_some_and(|x| local_now() as u64 >= x)
+    {
+        lock_account(account);
+    }
+}
+
+// Locks the current account if there has been no activity on it for longer
+// than its timeout; returns whether it is locked
+pub fn lock_if_idle() -> bool {
+    let account = current_account();
+    lock_account_if_idle(&account);
+
+    account.locked.load(Ordering::SeqCst)
+}
+
+pub fn check_unlocked() -> EnigmatickResult<()> {
+    if lock_if_idle() {
+        Err(EnigmatickError::Locked)
+    } else {
+        Ok(())
+    }
+}
+
+// Sets the idle period (in seconds) after which the session locks itself;
+// 0 turns the timeout off
+pub fn set_idle_timeout(seconds: u32) {
+    start_idle_timer(&current_account(), seconds as u64 * 1_000);
+}
+
+pub(crate) fn start_idle_timer(account: &Arc<Account>, timeout_ms: u64) {
+    account.idle.timeout_ms.store(timeout_ms, Ordering::SeqCst);
+    account.idle.note_activity();
+
+    let generation = account.idle.generation.fetch_add(1, Ordering::SeqCst) + 1;
+
+    #[cfg(target_arch = "wasm32")]
+    if timeout_ms > 0 {
+        wasm_bindgen_futures::spawn_local(watch_idle(Arc::downgrade(account), generation));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let _ = generation;
+}
+
+// Without this, an idle session would only lock on the next use of the secrets
+// and the key material would stay in memory until then. The watcher stops when
+// the timeout changes or the account is dropped.
+#[cfg(target_arch = "wasm32")]
+async fn watch_idle(account: std::sync::Weak<Account>, generation: u64) {
+    while let Some(account) = account.upgrade() {
+        if account.idle.generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+
+        let Some(deadline) = account.idle.deadline() else {
+            return;
+        };
+
+        let now = local_now() as u64;
+        let wait = if now >= deadline {
+            lock_account_if_idle(&account);
+            account.idle.timeout_ms().max(1_000)
+        } else {
+            deadline - now
+        };
+
+        // the account is not held while waiting
+        drop(account);
+        crate::sleep(wait).await;
+    }
+}
+
+// Restores the key material from the Profile already in state by deriving the
+// key from the password again; nothing is sent to the server. An incorrect
+// password is detected by the client key failing to decrypt.
+pub fn unlock(password: String) -> Result<(), EnigmatickError> {
+    let state = get_state();
+
+    if !state.authenticated {
+        return Err(EnigmatickError::NotAuthenticated);
+    }
+
+    let profile = state.profile.ok_or(EnigmatickError::NotAuthenticated)?;
+
+    if !is_locked() {
+        return Ok(());
+    }
+
+    let salt = profile
+        .salt
+        .clone()
+        .ok_or(EnigmatickError::missing("salt"))?;
+    let params = KdfParams::of(&profile);
+
+    let derived_key = encode_derived_key(&derive_key(password.clone(), salt.clone(), &params)?);
+
+    let client_private_key = decrypt(
+        Some(derived_key.clone()),
+        profile
+            .client_private_key
+            .clone()
+            .ok_or(EnigmatickError::missing("client_private_key"))?,
+    )
+    .map_err(|_| EnigmatickError::Decryption("incorrect password".to_string()))?;
+
+    let olm_pickled_account = profile
+        .olm_pickled_account
+        .clone()
+        .map(|x| decrypt(Some(derived_key.clone()), x))
+        .transpose()?;
+
+    let previous_keys = derive_previous_keys(password, salt, &params)?;
+
+    update_state(|state| {
+        state.set_client_private_key_pem(client_private_key);
+        if let Some(olm_pickled_account) = olm_pickled_account {
+            state.set_olm_pickled_account(olm_pickled_account);
+        }
+        state.set_previous_derived_keys(previous_keys);
+        // last, as this is what clears the lock
+        state.set_derived_key(derived_key);
+        Ok(())
+    })
+}
@@ -11,8 +11,8 @@ use wasm_bindgen::prelude::wasm_bindgen;
 
 use crate::{
     authenticated, decrypt_bytes, encrypt_bytes, get_mls_keys, get_state, log,
-    retrieve_credentials, send_get, send_post, update_instruments, EnigmatickState, Profile,
-    DECRYPT_FN, ENCRYPT_FN, HASH_FN,
+    retrieve_credentials, send_get, send_post, update_instruments, EnigmatickError,
+    EnigmatickResult, EnigmatickState, Profile, DECRYPT_FN, ENCRYPT_FN, HASH_FN,
 };
 
 // A helper to create and store credentials.
@@ -41,26 +41,29 @@ fn generate_credential_with_key(
     )
 }
 
-pub async fn get_mkp_collection() -> Option<ApCollection> {
+pub async fn get_mkp_collection() -> EnigmatickResult<ApCollection> {
     let response = authenticated(move |_: EnigmatickState, profile: Profile| async move {
         let username = profile.username;
         let path = format!("/user/{username}/keys?count=true");
 
         send_get(None, path, "application/activity+json".to_string()).await
     })
-    .await;
+    .await?;
 
-    response.and_then(|x| serde_json::from_str(&x).ok())
+    Ok(serde_json::from_str(&response)?)
 }
 
-#[wasm_bindgen]
-pub async fn replenish_mkp() -> Option<bool> {
+pub async fn replenish_mkp() -> Result<bool, EnigmatickError> {
     let mkp_collection = get_mkp_collection().await?;
 
     //log(&format!("{mkp_collection:#?}"));
 
-    if mkp_collection.total_items? < 20 {
-        let (credentials_key_pair, provider, mutation_of) = retrieve_credentials().await.ok()?;
+    let total_items = mkp_collection.total_items.ok_or(EnigmatickError::parse(
+        "key package collection has no totalItems",
+    ))?;
+
+    if total_items < 20 {
+        let (credentials_key_pair, provider, mutation_of) = retrieve_credentials().await?;
         let mut updated_instruments: Vec<ApInstrument> = generate_key_packages(
             &provider,
             &credentials_key_pair.key_pair,
@@ -76,10 +79,10 @@ pub async fn replenish_mkp() -> Option<bool> {
             ENCRYPT_FN,
             HASH_FN,
         )));
-        update_instruments(updated_instruments).await;
+        update_instruments(updated_instruments).await?;
     }
 
-    Some(true)
+    Ok(true)
 }
 
 // A helper to create key package bundles.
@@ -99,14 +102,14 @@ pub fn generate_key_packages(
         .collect()
 }
 
-pub async fn send_object(object: ApObject) -> Option<String> {
+pub async fn send_object(object: ApObject) -> EnigmatickResult<String> {
     authenticated(
         move |_state: EnigmatickState, profile: Profile| async move {
             let outbox = format!("/user/{}", profile.username.clone());
 
             send_post(
                 outbox,
-                serde_json::to_string(&object).unwrap(),
+                serde_json::to_string(&object)?,
                 "application/activity+json".to_string(),
             )
             .await
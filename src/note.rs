@@ -10,12 +10,18 @@ use std::{collections::HashMap, fmt::Debug};
 use wasm_bindgen::prelude::wasm_bindgen;
 
 use crate::{
-    authenticated, create_mls_group, error, get_state, get_string, log, send_get, send_post,
+    authenticated, create_mls_group, error, get_state, get_string, log, send_activity, send_get,
+    use_mls_group, EncryptedAttachment, EncryptedMessage, EnigmatickError, EnigmatickResult,
     EnigmatickState, Profile,
 };
 
 impl NoteParams {
-    pub async fn to_note(&mut self) -> ApNote {
+    // The plaintext of the MLS application message
+    pub fn get_message(&self) -> EnigmatickResult<String> {
+        EncryptedMessage::new(self.content.clone(), self.encrypted_attachments.clone()).encode()
+    }
+
+    pub async fn to_note(&mut self) -> EnigmatickResult<ApNote> {
         //log(&format!("params\n{self:#?}"));
         let state = get_state();
         let mut encrypted = false;
@@ -106,13 +112,20 @@ impl NoteParams {
                         //log(&format!("Sending to single address : {_address}"));
                         encrypted = *enigmatick;
                         if encrypted {
-                            encrypt_note(self).await.unwrap();
+                            encrypt_note(self).await?;
                         }
                     }
                 }
             }
         }
 
+        // the keys would otherwise be sent nowhere, or in the clear
+        if !encrypted && !self.encrypted_attachments.is_empty() {
+            return Err(EnigmatickError::InvalidInput(
+                "encrypted attachments can only be sent in encrypted notes".to_string(),
+            ));
+        }
+
         let instrument = {
             if encrypted {
                 let instruments = self.get_instruments();
@@ -136,7 +149,7 @@ impl NoteParams {
             None
         };
 
-        ApNote {
+        Ok(ApNote {
             context: Some(ApContext::default()),
             id: self.id.clone(),
             kind: if encrypted {
@@ -171,34 +184,32 @@ impl NoteParams {
             instrument,
             source,
             ..Default::default()
-        }
+        })
     }
 }
 
-#[wasm_bindgen]
-pub async fn get_local_conversation(uuid: String) -> Option<String> {
+pub async fn get_local_conversation(uuid: String) -> Result<String, EnigmatickError> {
     let response = get_string(
         format!("/conversation/{uuid}"),
         None,
         "application/activity+json",
     )
-    .await
-    .ok()??;
+    .await?;
 
     if let Ok(ApObject::Collection(object)) = serde_json::from_str(&response) {
-        object
+        let items = object
             .items()
-            .map(|items| serde_json::to_string(&items).unwrap())
+            .ok_or(EnigmatickError::parse("conversation has no items"))?;
+        Ok(serde_json::to_string(&items)?)
     } else {
         error(&format!(
             "Failed to convert text to Collection: {response:?}"
         ));
-        None
+        Err(EnigmatickError::parse("failed to convert text to Collection"))
     }
 }
 
-#[wasm_bindgen]
-pub async fn get_note(id: String) -> Option<String> {
+pub async fn get_note(id: String) -> Result<String, EnigmatickError> {
     let path = format!("/api/remote/object?id={}", urlencoding::encode(&id));
 
     send_get(None, path, "application/json".to_string()).await
@@ -217,6 +228,7 @@ pub struct NoteParams {
     in_reply_to: Option<String>,
     conversation: Option<String>,
     attachments: Option<String>,
+    encrypted_attachments: Vec<EncryptedAttachment>,
     source_content: Option<String>,
     source_media_type: Option<String>,
     instruments: Vec<ApInstrument>,
@@ -338,6 +350,15 @@ impl NoteParams {
         self.clone()
     }
 
+    // attachment is the JSON returned by upload_encrypted_attachment
+    pub fn add_encrypted_attachment(
+        &mut self,
+        attachment: String,
+    ) -> Result<NoteParams, EnigmatickError> {
+        self.encrypted_attachments.push(serde_json::from_str(&attachment)?);
+        Ok(self.clone())
+    }
+
     pub fn set_preserve_to(&mut self, to_json: String) -> Self {
         self.preserve_to = Some(to_json);
         self.clone()
@@ -865,8 +886,8 @@ impl QuestionParams {
 }
 
 pub async fn encrypt_note(params: &mut NoteParams) -> Result<()> {
-    if params.conversation.is_some() {
-        //use_mls_group(params).await;
+    if let Some(conversation) = params.conversation.clone() {
+        use_mls_group(params, conversation).await?;
     } else {
         create_mls_group(params).await?;
     };
@@ -876,39 +897,40 @@ pub async fn encrypt_note(params: &mut NoteParams) -> Result<()> {
     Ok(())
 }
 
-#[wasm_bindgen]
-pub async fn send_note(params: &mut NoteParams) -> Option<String> {
+pub async fn send_note(params: &mut NoteParams) -> Result<String, EnigmatickError> {
     authenticated(move |state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
 
         let id = format!(
             "{}/user/{}",
-            state.server_url.unwrap(),
+            state
+                .server_url
+                .ok_or(EnigmatickError::missing("server_url"))?,
             profile.username.clone()
         );
-        let mut note = params.clone().to_note().await;
+        let mut note = params.clone().to_note().await?;
         note.attributed_to = id.into();
 
         log(&format!("NOTE\n{}", serde_json::to_string(&note).unwrap()));
 
-        send_post(
-            outbox,
-            serde_json::to_string(&note).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &note).await
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn send_vote(option_name: String, question_id: String, question_author: String) -> Option<String> {
+pub async fn send_vote(
+    option_name: String,
+    question_id: String,
+    question_author: String,
+) -> Result<String, EnigmatickError> {
     authenticated(move |state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
 
         let actor_id = format!(
             "{}/user/{}",
-            state.server_url.unwrap(),
+            state
+                .server_url
+                .ok_or(EnigmatickError::missing("server_url"))?,
             profile.username.clone()
         );
 
@@ -926,30 +948,20 @@ pub async fn send_vote(option_name: String, question_id: String, question_author
 
         log(&format!("VOTE NOTE\n{}", serde_json::to_string(&vote_note).unwrap()));
 
-        send_post(
-            outbox,
-            serde_json::to_string(&vote_note).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &vote_note).await
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn send_question(question_json: String) -> Option<String> {
+pub async fn send_question(question_json: String) -> Result<String, EnigmatickError> {
     authenticated(move |_state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
 
         log(&format!("QUESTION JSON\n{}", question_json));
 
         // Send just the Question object - server will wrap it in a Create Activity
-        send_post(
-            outbox,
-            question_json,
-            "application/activity+json".to_string(),
-        )
-        .await
+        let question: serde_json::Value = serde_json::from_str(&question_json)?;
+        send_activity(outbox, &question).await
     })
     .await
 }
@@ -1,13 +1,11 @@
-use crate::{get_object, get_state, log, send_get};
+use crate::{get_object, get_state, log, send_get, EnigmatickError};
 use jdt_activity_pub::ApCollection;
-use wasm_bindgen::prelude::wasm_bindgen;
 
-#[wasm_bindgen]
 pub async fn get_outbox(
     username: String,
     kind: Option<String>,
     timestamp: Option<String>,
-) -> Option<String> {
+) -> Result<String, EnigmatickError> {
     //log(&format!("REQUEST {username}"));
     //let (username, limit, kind, timestamp) = extract_outbox_elements(url);
 
@@ -16,22 +14,25 @@ pub async fn get_outbox(
     //));
 
     let outbox = match (kind, timestamp) {
-        (Some(kind), Some(timestamp)) => Some(format!(
-            "/user/{username}/outbox?page=true&{kind}={timestamp}"
-        )),
-        (None, None) => Some(format!("/user/{username}/outbox?page=true")),
-        _ => None,
+        (Some(kind), Some(timestamp)) => {
+            format!("/user/{username}/outbox?page=true&{kind}={timestamp}")
+        }
+        (None, None) => format!("/user/{username}/outbox?page=true"),
+        _ => {
+            return Err(EnigmatickError::InvalidInput(
+                "kind and timestamp must be provided together".to_string(),
+            ))
+        }
     };
 
     //log(&format!("OUTBOX {outbox:#?}"));
 
     if get_state().authenticated {
-        send_get(None, outbox?, "application/activity+json".to_string()).await
+        send_get(None, outbox, "application/activity+json".to_string()).await
     } else {
-        let collection: ApCollection = get_object(outbox?, None, "application/activity+json")
-            .await
-            .ok()?;
+        let collection: ApCollection =
+            get_object(outbox, None, "application/activity+json").await?;
 
-        serde_json::to_string(&collection).ok()
+        Ok(serde_json::to_string(&collection)?)
     }
 }
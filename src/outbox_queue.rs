@@ -0,0 +1,258 @@
+use chrono::Utc;
+use futures::lock::Mutex;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+#[cfg(target_arch = "wasm32")]
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use uuid::Uuid;
+
+use crate::{
+    activity_delivered, current_account, deliver_activity, get_state, log, storage_load,
+    storage_remove, storage_save, EnigmatickError, EnigmatickResult, RetryPolicy,
+};
+
+lazy_static! {
+    // serializes load/modify/save cycles against the persisted queue
+    static ref QUEUE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[cfg(target_arch = "wasm32")]
+static LISTENING: AtomicBool = AtomicBool::new(false);
+
+// An outbound activity that could not be delivered when it was sent. The
+// activity is stored fully built (including its client-assigned ID) so that
+// replaying it is identical to the original attempt.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueuedActivity {
+    pub id: String,
+    pub outbox: String,
+    pub activity: Value,
+    pub idempotent: bool,
+    pub queued_at: String,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    // set when the server rejected the activity outright; failed entries are
+    // not retried automatically and stay until they are cancelled
+    #[serde(default)]
+    pub failed: bool,
+}
+
+// Keyed by actor ID, as the same username may be signed in on more than one
+// server. Queues stored under the username by earlier versions are moved over
+// the first time they are used; callers must hold QUEUE_LOCK.
+async fn queue_key() -> EnigmatickResult<String> {
+    let profile = get_state()
+        .profile
+        .ok_or(EnigmatickError::NotAuthenticated)?;
+
+    let key = format!("outbox_queue.{}", profile.id);
+    let legacy = format!("outbox_queue.{}", profile.username);
+
+    if let Some(data) = storage_load(&legacy).await? {
+        if storage_load(&key).await?.is_none() {
+            storage_save(&key, &data).await?;
+        }
+        storage_remove(&legacy).await?;
+    }
+
+    Ok(key)
+}
+
+async fn load_queue(key: &str) -> EnigmatickResult<Vec<QueuedActivity>> {
+    match storage_load(key).await? {
+        Some(data) => Ok(serde_json::from_str(&data)?),
+        None => Ok(vec![]),
+    }
+}
+
+async fn update_queue<F, T>(f: F) -> EnigmatickResult<T>
+where
+    F: FnOnce(&mut Vec<QueuedActivity>) -> T,
+{
+    let _guard = QUEUE_LOCK.lock().await;
+    let key = queue_key().await?;
+
+    let mut queue = load_queue(&key).await?;
+    let result = f(&mut queue);
+    storage_save(&key, &serde_json::to_string(&queue)?).await?;
+
+    Ok(result)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn is_online() -> bool {
+    web_sys::window()
+        .map(|x| x.navigator().on_line())
+        .unwrap_or(true)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn is_online() -> bool {
+    true
+}
+
+pub async fn queue_activity(
+    outbox: String,
+    activity: Value,
+    idempotent: bool,
+    error: Option<String>,
+) -> EnigmatickResult<String> {
+    let id = Uuid::new_v4().to_string();
+
+    let item = QueuedActivity {
+        id: id.clone(),
+        outbox,
+        activity,
+        idempotent,
+        queued_at: Utc::now().to_rfc3339(),
+        attempts: if error.is_some() { 1 } else { 0 },
+        last_error: error,
+        failed: false,
+    };
+
+    update_queue(move |queue| queue.push(item)).await?;
+    listen_for_connectivity();
+
+    Ok(id)
+}
+
+// Drains the queue in the background after a successful request or login.
+// Transport futures are not Send, so natively the drain gets its own thread
+// on the caller's tokio runtime; without one the queue waits for an explicit
+// drain_outbox_queue.
+#[cfg(target_arch = "wasm32")]
+pub async fn schedule_outbox_drain() {
+    spawn_drain();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn schedule_outbox_drain() {
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        let client = crate::Client::active();
+        std::thread::spawn(move || {
+            handle.block_on(client.run(async {
+                drain_outbox_queue().await.ok();
+            }))
+        });
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn spawn_drain() {
+    wasm_bindgen_futures::spawn_local(crate::with_current_client(async {
+        drain_outbox_queue().await.ok();
+    }));
+}
+
+#[cfg(target_arch = "wasm32")]
+fn listen_for_connectivity() {
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    if LISTENING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    if let Some(window) = web_sys::window() {
+        let handler = Closure::<dyn Fn()>::new(spawn_drain);
+        window
+            .add_event_listener_with_callback("online", handler.as_ref().unchecked_ref())
+            .ok();
+        handler.forget();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn listen_for_connectivity() {}
+
+// Attempts delivery of every pending entry in queue order. Stops at the first
+// transient failure (we are presumably still offline) so that the order of
+// delivery is preserved. Returns the number of activities delivered.
+pub async fn drain_outbox_queue() -> Result<u32, EnigmatickError> {
+    listen_for_connectivity();
+
+    let account = current_account();
+    if account.draining.swap(true, Ordering::SeqCst) {
+        return Ok(0);
+    }
+
+    let result = drain().await;
+    account.draining.store(false, Ordering::SeqCst);
+    result
+}
+
+async fn drain() -> EnigmatickResult<u32> {
+    let mut delivered = 0;
+
+    loop {
+        let next = {
+            let _guard = QUEUE_LOCK.lock().await;
+            let key = queue_key().await?;
+            load_queue(&key).await?.into_iter().find(|x| !x.failed)
+        };
+
+        let Some(item) = next else {
+            return Ok(delivered);
+        };
+
+        let body = serde_json::to_string(&item.activity)?;
+        let result = deliver_activity(
+            &item.outbox,
+            &body,
+            item.idempotent,
+            &RetryPolicy::default(),
+        )
+        .await;
+
+        match result {
+            Ok(_) => {
+                update_queue(|queue| queue.retain(|x| x.id != item.id)).await?;
+                activity_delivered(&item.activity, Some(item.id.clone()));
+                delivered += 1;
+            }
+            Err(e) => {
+                let transient = e.is_transient();
+                log(&format!("queued activity {} not delivered: {e}", item.id));
+
+                update_queue(|queue| {
+                    if let Some(x) = queue.iter_mut().find(|x| x.id == item.id) {
+                        x.attempts += 1;
+                        x.last_error = Some(e.to_string());
+                        x.failed = !transient;
+                    }
+                })
+                .await?;
+
+                if transient {
+                    return Ok(delivered);
+                }
+            }
+        }
+    }
+}
+
+pub async fn get_outbox_queue() -> Result<String, EnigmatickError> {
+    let _guard = QUEUE_LOCK.lock().await;
+    let key = queue_key().await?;
+
+    Ok(serde_json::to_string(&load_queue(&key).await?)?)
+}
+
+// Moves the listed entries to the front of the queue in the order given;
+// entries that are not listed keep their relative order after them
+pub async fn reorder_outbox_queue(ids: Vec<String>) -> Result<(), EnigmatickError> {
+    update_queue(move |queue| {
+        queue.sort_by_key(|x| ids.iter().position(|id| *id == x.id).unwrap_or(ids.len()));
+    })
+    .await
+}
+
+pub async fn cancel_queued_activity(id: String) -> Result<bool, EnigmatickError> {
+    update_queue(move |queue| {
+        let before = queue.len();
+        queue.retain(|x| x.id != id);
+        queue.len() != before
+    })
+    .await
+}
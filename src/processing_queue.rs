@@ -2,7 +2,7 @@ use jdt_activity_pub::ApObject;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::{authenticated, log, send_get, send_post, EnigmatickState, Profile};
+use crate::{authenticated, log, send_get, send_post, EnigmatickError, EnigmatickState, Profile};
 
 #[wasm_bindgen]
 #[derive(Serialize, Deserialize, Default, Clone, Debug)]
@@ -19,8 +19,7 @@ pub struct QueueAction {
     action: QueueTask,
 }
 
-#[wasm_bindgen]
-pub async fn resolve_processed_item(id: String) -> Option<String> {
+pub async fn resolve_processed_item(id: String) -> Result<String, EnigmatickError> {
     //log("IN resolve_processed_item");
 
     authenticated(
@@ -34,7 +33,7 @@ pub async fn resolve_processed_item(id: String) -> Option<String> {
 
             send_post(
                 url,
-                serde_json::to_string(&data).unwrap(),
+                serde_json::to_string(&data)?,
                 "application/json".to_string(),
             )
             .await
@@ -43,8 +42,7 @@ pub async fn resolve_processed_item(id: String) -> Option<String> {
     .await
 }
 
-#[wasm_bindgen]
-pub async fn get_processing_queue() -> Option<String> {
+pub async fn get_processing_queue() -> Result<String, EnigmatickError> {
     //log("IN get processing_queue");
 
     authenticated(
@@ -53,10 +51,10 @@ pub async fn get_processing_queue() -> Option<String> {
 
             let data = send_get(None, url, "application/activity+json".to_string()).await?;
 
-            if let ApObject::Collection(object) = serde_json::from_str::<ApObject>(&data).ok()? {
-                Some(serde_json::to_string(&object).ok()?)
+            if let ApObject::Collection(object) = serde_json::from_str::<ApObject>(&data)? {
+                Ok(serde_json::to_string(&object)?)
             } else {
-                None
+                Err(EnigmatickError::parse("queue response is not a Collection"))
             }
         },
     )
@@ -0,0 +1,324 @@
+use base64::{engine::general_purpose, engine::Engine as _};
+use bip39::Mnemonic;
+use jdt_activity_pub::ApInstrument;
+use openmls::prelude::OpenMlsProvider;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{
+    authenticated, begin_account, decrypt, derive_key, encode_derived_key, encrypt, get_state,
+    get_vault_items, open_instrument, post_object, seal_instrument, send_post, srp_prove,
+    srp_verifier, store_vault_items, stored_credentials, update_instruments, update_state,
+    with_secrets, EnigmatickError, EnigmatickResult, EnigmatickState, KdfParams, Profile,
+    SrpVerifier, ENCRYPT_FN, HASH_FN,
+};
+
+// A BIP-39 phrase that wraps the derived key and proves itself to the server with SRP
+
+const RECOVERY_ENTROPY_BYTES: usize = 32;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecoveryWrap {
+    pub recovery_key: String,
+    pub recovery_escrow: String,
+}
+
+impl RecoveryWrap {
+    pub fn apply(&self, profile: &mut Profile) {
+        profile.recovery_key = Some(self.recovery_key.clone());
+        profile.recovery_escrow = Some(self.recovery_escrow.clone());
+    }
+}
+
+// What the server stores when a phrase is set up
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecoverySetup {
+    #[serde(flatten)]
+    pub wrap: RecoveryWrap,
+    pub recovery_srp_salt: String,
+    pub recovery_srp_verifier: String,
+}
+
+struct RecoverySecrets {
+    wrapping_key: String,
+    srp_password: String,
+}
+
+fn recovery_secrets(phrase: &str) -> EnigmatickResult<RecoverySecrets> {
+    let mnemonic = Mnemonic::parse(phrase)
+        .map_err(|e| EnigmatickError::InvalidInput(format!("invalid recovery phrase: {e}")))?;
+    let entropy = mnemonic.to_entropy();
+
+    let derive = |purpose: &[u8]| {
+        let mut hasher = Sha256::new();
+        hasher.update(purpose);
+        hasher.update(&entropy);
+        general_purpose::STANDARD.encode(hasher.finalize())
+    };
+
+    Ok(RecoverySecrets {
+        wrapping_key: derive(b"enigmatick recovery wrapping key"),
+        srp_password: derive(b"enigmatick recovery srp password"),
+    })
+}
+
+fn wrap(wrapping_key: &str, derived_key: &str) -> EnigmatickResult<RecoveryWrap> {
+    Ok(RecoveryWrap {
+        recovery_key: encrypt(Some(wrapping_key.to_string()), derived_key.to_string())?,
+        recovery_escrow: encrypt(Some(derived_key.to_string()), wrapping_key.to_string())?,
+    })
+}
+
+// Shown to the user once; it is not stored anywhere by the client
+#[wasm_bindgen]
+pub fn generate_recovery_phrase() -> Result<String, EnigmatickError> {
+    let mut entropy = [0u8; RECOVERY_ENTROPY_BYTES];
+    rand::thread_rng().fill_bytes(&mut entropy);
+
+    Mnemonic::from_entropy(&entropy)
+        .map(|x| x.to_string())
+        .map_err(|e| EnigmatickError::Internal(e.to_string()))
+}
+
+pub fn recovery_setup(
+    username: &str,
+    phrase: &str,
+    derived_key: &str,
+) -> EnigmatickResult<RecoverySetup> {
+    let secrets = recovery_secrets(phrase)?;
+    let SrpVerifier {
+        srp_salt,
+        srp_verifier,
+    } = srp_verifier(username, &secrets.srp_password);
+
+    Ok(RecoverySetup {
+        wrap: wrap(&secrets.wrapping_key, derived_key)?,
+        recovery_srp_salt: srp_salt,
+        recovery_srp_verifier: srp_verifier,
+    })
+}
+
+// The recovery copy for a new derived key, if the account has a phrase
+pub fn rewrap_recovery(
+    profile: &Profile,
+    old_derived_key: &str,
+    new_derived_key: &str,
+) -> EnigmatickResult<Option<RecoveryWrap>> {
+    let Some(escrow) = profile.recovery_escrow.clone() else {
+        return Ok(None);
+    };
+
+    let wrapping_key = decrypt(Some(old_derived_key.to_string()), escrow)?;
+    Ok(Some(wrap(&wrapping_key, new_derived_key)?))
+}
+
+// Sets up (or replaces) the recovery phrase for the logged-in account
+pub async fn add_recovery_phrase(phrase: String) -> Result<(), EnigmatickError> {
+    authenticated(move |_: EnigmatickState, profile: Profile| async move {
+        let setup = with_secrets(|secrets| {
+            recovery_setup(&profile.username, &phrase, secrets.derived_key()?.expose())
+        })?;
+
+        let url = format!("/api/user/{}/recovery", profile.username);
+        send_post(
+            url,
+            serde_json::to_string(&setup)?,
+            "application/json".to_string(),
+        )
+        .await?;
+
+        update_state(|state| {
+            if let Some(profile) = state.profile.as_mut() {
+                setup.wrap.apply(profile);
+            }
+            Ok(())
+        })
+    })
+    .await
+}
+
+// Resets a forgotten password: the phrase is proven to the server, the old
+// derived key is unwrapped from the recovery copy, and the client key, olm
+// account and MLS instruments are stored again under the key derived from the
+// new password. The phrase remains valid afterward.
+pub async fn recover_account(
+    username: String,
+    phrase: String,
+    password_str: String,
+) -> Result<Profile, EnigmatickError> {
+    #[derive(Serialize)]
+    struct RecoveryData {
+        username: String,
+        session: String,
+        m1: String,
+    }
+
+    #[derive(Deserialize)]
+    struct Recovered {
+        profile: Profile,
+        m2: String,
+    }
+
+    #[derive(Serialize)]
+    struct PasswordReset {
+        #[serde(flatten)]
+        srp: SrpVerifier,
+        encrypted_client_private_key: String,
+        encrypted_olm_pickled_account: Option<String>,
+        kdf: KdfParams,
+        #[serde(flatten)]
+        recovery: RecoveryWrap,
+    }
+
+    let secrets = recovery_secrets(&phrase)?;
+
+    let proof = srp_prove(
+        "/api/user/recover/start".to_string(),
+        &username,
+        &secrets.srp_password,
+    )
+    .await?;
+    let response = post_object(
+        "/api/user/recover".to_string(),
+        RecoveryData {
+            username,
+            session: proof.session.clone(),
+            m1: proof.m1.clone(),
+        },
+        "application/json",
+        None,
+    )
+    .await?;
+
+    let recovered: Recovered = serde_json::from_str(&response)?;
+    proof.verify_server(&recovered.m2)?;
+    let profile = recovered.profile;
+
+    let old_key = decrypt(
+        Some(secrets.wrapping_key.clone()),
+        profile
+            .recovery_key
+            .clone()
+            .ok_or(EnigmatickError::missing("recovery_key"))?,
+    )?;
+    let client_private_key = decrypt(
+        Some(old_key.clone()),
+        profile
+            .client_private_key
+            .clone()
+            .ok_or(EnigmatickError::missing("client_private_key"))?,
+    )?;
+    let olm_pickled_account = profile
+        .olm_pickled_account
+        .clone()
+        .map(|x| decrypt(Some(old_key.clone()), x))
+        .transpose()?;
+
+    let salt = profile
+        .salt
+        .clone()
+        .ok_or(EnigmatickError::missing("salt"))?;
+    let params = KdfParams::current();
+    let new_key = encode_derived_key(&derive_key(password_str.clone(), salt, &params)?);
+
+    begin_account();
+
+    // an earlier attempt with the same password may have sealed some of the
+    // instruments under the new key before it failed
+    update_state(|state| {
+        state.authenticated = true;
+        state.set_profile(profile.clone());
+        state.set_derived_key(old_key.clone());
+        state.set_previous_derived_keys(vec![new_key.clone()]);
+        state.set_client_private_key_pem(client_private_key.clone());
+        if let Some(olm_pickled_account) = olm_pickled_account.clone() {
+            state.set_olm_pickled_account(olm_pickled_account);
+        }
+        Ok(())
+    })?;
+
+    // the MLS instruments and vault have to be opened while the old key is
+    // current; anything that cannot be opened stops the reset
+    let mls = stored_credentials().await?;
+    let vault = get_vault_items(&profile)
+        .await?
+        .into_iter()
+        .map(open_instrument)
+        .collect::<EnigmatickResult<Vec<ApInstrument>>>()?;
+
+    let reset = PasswordReset {
+        srp: srp_verifier(&profile.username, &password_str),
+        encrypted_client_private_key: encrypt(Some(new_key.clone()), client_private_key)?,
+        encrypted_olm_pickled_account: olm_pickled_account
+            .map(|x| encrypt(Some(new_key.clone()), x))
+            .transpose()?,
+        kdf: params.clone(),
+        recovery: wrap(&secrets.wrapping_key, &new_key)?,
+    };
+
+    // Everything is stored under the new key before the server is told about
+    // it; until then the recovery copy still unwraps the old key, which opens
+    // whatever was sealed (the new key is kept as a previous key for a retry)
+    let committed = async {
+        update_state(|state| {
+            state.set_derived_key(new_key.clone());
+            state.set_previous_derived_keys(vec![old_key.clone()]);
+            Ok(())
+        })?;
+
+        if let Some((credentials, provider, mutation_of)) = mls {
+            update_instruments(vec![
+                ApInstrument::from((credentials, ENCRYPT_FN)),
+                ApInstrument::from((provider.storage(), mutation_of, ENCRYPT_FN, HASH_FN)),
+            ])
+            .await?;
+        }
+
+        let vault = vault
+            .into_iter()
+            .map(seal_instrument)
+            .collect::<EnigmatickResult<Vec<ApInstrument>>>()?;
+
+        if !vault.is_empty() {
+            store_vault_items(&profile, vault).await?;
+        }
+
+        let url = format!("/api/user/{}/password/reset", profile.username);
+        send_post(
+            url,
+            serde_json::to_string(&reset)?,
+            "application/json".to_string(),
+        )
+        .await
+    }
+    .await;
+
+    if let Err(e) = committed {
+        // the server still holds the client key under the old key
+        update_state(|state| {
+            state.set_derived_key(old_key.clone());
+            state.set_previous_derived_keys(vec![new_key.clone()]);
+            Ok(())
+        })?;
+
+        return Err(e);
+    }
+
+    update_state(|state| {
+        // nothing is left under the old key
+        state.set_previous_derived_keys(vec![]);
+        if let Some(profile) = state.profile.as_mut() {
+            profile.client_private_key = Some(reset.encrypted_client_private_key.clone());
+            profile.olm_pickled_account = reset.encrypted_olm_pickled_account.clone();
+            profile.kdf = Some(params);
+            reset.recovery.apply(profile);
+        }
+        Ok(())
+    })?;
+
+    get_state()
+        .profile
+        .ok_or(EnigmatickError::missing("profile"))
+}
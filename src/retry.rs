@@ -0,0 +1,98 @@
+use rand::Rng;
+
+use crate::{server_now, EnigmatickError, HttpResponse};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    // the global setTimeout rather than window.setTimeout so that this also
+    // works when the component is loaded in a Web Worker
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(handler: &js_sys::Function, timeout: i32) -> JsValue;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub async fn sleep(ms: u64) {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        set_timeout(&resolve, ms.min(i32::MAX as u64) as i32);
+    });
+
+    wasm_bindgen_futures::JsFuture::from(promise).await.ok();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn sleep(ms: u64) {
+    tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+}
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    // total number of attempts, including the first
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    // Exponential backoff with up to 50% jitter; attempt is 1 for the delay
+    // after the first failure
+    pub fn backoff(&self, attempt: u32) -> u64 {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let delay = self
+            .base_delay_ms
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay_ms);
+        let jitter = rand::thread_rng().gen_range(0..=delay / 2);
+
+        (delay + jitter).min(self.max_delay_ms)
+    }
+
+    // An idempotent request (one carrying a client-assigned activity ID) may be
+    // retried after any transient failure. Anything else is only retried when
+    // the server has told us it did not process the request.
+    pub fn should_retry(&self, error: &EnigmatickError, attempt: u32, idempotent: bool) -> bool {
+        if attempt >= self.max_attempts {
+            return false;
+        }
+
+        if idempotent {
+            error.is_transient()
+        } else {
+            matches!(error.status(), Some(429) | Some(503))
+        }
+    }
+}
+
+// Retry-After may be either a number of seconds or an HTTP-date
+pub fn retry_after(response: &HttpResponse) -> Option<u64> {
+    let value = response.header("Retry-After")?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(seconds.saturating_mul(1_000));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    let target = date.duration_since(std::time::UNIX_EPOCH).ok()?.as_millis() as f64;
+
+    Some((target - server_now()).max(0.0) as u64)
+}
@@ -0,0 +1,584 @@
+use orion::aead::streaming::{Nonce, StreamOpener, StreamSealer, StreamTag, ABYTES};
+use orion::aead::SecretKey;
+use std::io::{self, Read, Write};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{crypto::secret_key, EnigmatickError, EnigmatickResult};
+
+// Chunked XChaCha20-Poly1305 secretstream: MAGIC | chunk size (u32, BE) | nonce | chunks
+
+const MAGIC: &[u8; 4] = b"EKS1";
+const NONCE_BYTES: usize = 24;
+const HEADER_BYTES: usize = MAGIC.len() + 4 + NONCE_BYTES;
+
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+fn stream_error(x: &str) -> EnigmatickError {
+    EnigmatickError::Decryption(format!("encrypted stream {x}"))
+}
+
+// Encrypts a stream of bytes: each push returns whatever ciphertext is ready,
+// and finish returns the rest
+#[wasm_bindgen]
+pub struct StreamEncryptor {
+    sealer: StreamSealer,
+    header: Option<Vec<u8>>,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+#[wasm_bindgen]
+impl StreamEncryptor {
+    // Without a key, the logged-in account's derived key is used
+    #[wasm_bindgen(constructor)]
+    pub fn new(derived_key: Option<String>) -> Result<StreamEncryptor, EnigmatickError> {
+        let (sealer, nonce) =
+            StreamSealer::new(&secret_key(derived_key)?).map_err(EnigmatickError::encryption)?;
+
+        let mut header = MAGIC.to_vec();
+        header.extend((STREAM_CHUNK_SIZE as u32).to_be_bytes());
+        header.extend(nonce.as_ref());
+
+        Ok(StreamEncryptor {
+            sealer,
+            header: Some(header),
+            buffer: Vec::with_capacity(STREAM_CHUNK_SIZE),
+            finished: false,
+        })
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, EnigmatickError> {
+        if self.finished {
+            return Err(EnigmatickError::InvalidInput(
+                "encrypted stream is already finished".to_string(),
+            ));
+        }
+
+        let mut output = self.header.take().unwrap_or_default();
+        let mut data = data;
+
+        while !data.is_empty() {
+            // a full chunk is only sealed once more data arrives; the last
+            // chunk has to be sealed with the Finish tag instead
+            if self.buffer.len() == STREAM_CHUNK_SIZE {
+                output.extend(self.seal(StreamTag::Message)?);
+            }
+
+            let take = (STREAM_CHUNK_SIZE - self.buffer.len()).min(data.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+        }
+
+        Ok(output)
+    }
+
+    pub fn finish(&mut self) -> Result<Vec<u8>, EnigmatickError> {
+        if self.finished {
+            return Ok(vec![]);
+        }
+
+        let mut output = self.header.take().unwrap_or_default();
+        output.extend(self.seal(StreamTag::Finish)?);
+        self.finished = true;
+
+        Ok(output)
+    }
+}
+
+impl StreamEncryptor {
+    fn seal(&mut self, tag: StreamTag) -> EnigmatickResult<Vec<u8>> {
+        let sealed = self
+            .sealer
+            .seal_chunk(&self.buffer, &tag)
+            .map_err(EnigmatickError::encryption)?;
+        self.buffer.clear();
+
+        Ok(sealed)
+    }
+}
+
+// Decrypts what StreamEncryptor produced; input can be pushed in pieces of
+// any size. Plaintext is returned as each chunk is authenticated, so callers
+// must not act on it until finish succeeds.
+#[wasm_bindgen]
+pub struct StreamDecryptor {
+    key: SecretKey,
+    opener: Option<StreamOpener>,
+    sealed_chunk_size: usize,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+#[wasm_bindgen]
+impl StreamDecryptor {
+    #[wasm_bindgen(constructor)]
+    pub fn new(derived_key: Option<String>) -> Result<StreamDecryptor, EnigmatickError> {
+        Ok(StreamDecryptor {
+            key: secret_key(derived_key)?,
+            opener: None,
+            sealed_chunk_size: 0,
+            buffer: vec![],
+            finished: false,
+        })
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, EnigmatickError> {
+        if self.finished && !data.is_empty() {
+            return Err(stream_error("has data after its end"));
+        }
+
+        self.buffer.extend_from_slice(data);
+
+        if self.opener.is_none() {
+            if self.buffer.len() < HEADER_BYTES {
+                return Ok(vec![]);
+            }
+            self.open_header()?;
+        }
+
+        let mut output = vec![];
+        while self.buffer.len() >= self.sealed_chunk_size {
+            let chunk: Vec<u8> = self.buffer.drain(..self.sealed_chunk_size).collect();
+            output.extend(self.open(&chunk)?);
+
+            if self.finished && !self.buffer.is_empty() {
+                return Err(stream_error("has data after its end"));
+            }
+        }
+
+        Ok(output)
+    }
+
+    pub fn finish(&mut self) -> Result<Vec<u8>, EnigmatickError> {
+        if self.finished {
+            return Ok(vec![]);
+        }
+
+        if self.opener.is_none() || self.buffer.is_empty() {
+            return Err(stream_error("is truncated"));
+        }
+
+        let chunk = std::mem::take(&mut self.buffer);
+        let output = self.open(&chunk)?;
+
+        if !self.finished {
+            return Err(stream_error("is truncated"));
+        }
+
+        Ok(output)
+    }
+}
+
+impl StreamDecryptor {
+    fn open_header(&mut self) -> EnigmatickResult<()> {
+        let header: Vec<u8> = self.buffer.drain(..HEADER_BYTES).collect();
+
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(stream_error("has an unrecognized header"));
+        }
+
+        let mut chunk_size = [0u8; 4];
+        chunk_size.copy_from_slice(&header[MAGIC.len()..MAGIC.len() + 4]);
+        let chunk_size = u32::from_be_bytes(chunk_size) as usize;
+
+        if chunk_size == 0 || chunk_size > 16 * STREAM_CHUNK_SIZE {
+            return Err(stream_error("has an invalid chunk size"));
+        }
+
+        let nonce =
+            Nonce::from_slice(&header[MAGIC.len() + 4..]).map_err(EnigmatickError::decryption)?;
+
+        self.opener =
+            Some(StreamOpener::new(&self.key, &nonce).map_err(EnigmatickError::decryption)?);
+        self.sealed_chunk_size = chunk_size + ABYTES;
+
+        Ok(())
+    }
+
+    fn open(&mut self, chunk: &[u8]) -> EnigmatickResult<Vec<u8>> {
+        let opener = self.opener.as_mut().ok_or(stream_error("is truncated"))?;
+
+        let (plaintext, tag) = opener
+            .open_chunk(chunk)
+            .map_err(EnigmatickError::decryption)?;
+
+        if tag == StreamTag::Finish {
+            self.finished = true;
+        } else if chunk.len() < self.sealed_chunk_size {
+            // only the last chunk may be short
+            return Err(stream_error("is truncated"));
+        }
+
+        Ok(plaintext)
+    }
+}
+
+// The nonce from the header of an encrypted stream
+pub fn stream_nonce(sealed: &[u8]) -> Option<&[u8]> {
+    if sealed.len() < HEADER_BYTES || &sealed[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+
+    Some(&sealed[MAGIC.len() + 4..HEADER_BYTES])
+}
+
+fn io_error(e: EnigmatickError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+// Encrypts everything written to it into inner; finish must be called to
+// write the final chunk
+pub struct EncryptWriter<W: Write> {
+    inner: W,
+    encryptor: StreamEncryptor,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    pub fn new(inner: W, derived_key: Option<String>) -> EnigmatickResult<Self> {
+        Ok(EncryptWriter {
+            inner,
+            encryptor: StreamEncryptor::new(derived_key)?,
+        })
+    }
+
+    pub fn finish(mut self) -> EnigmatickResult<W> {
+        let output = self.encryptor.finish()?;
+        self.inner.write_all(&output)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let output = self.encryptor.push(buf).map_err(io_error)?;
+        self.inner.write_all(&output)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Reads the plaintext of an encrypted stream from inner. Reaching the end of
+// inner before the final chunk is an error rather than a short read.
+pub struct DecryptReader<R: Read> {
+    inner: R,
+    decryptor: StreamDecryptor,
+    pending: Vec<u8>,
+    position: usize,
+    done: bool,
+}
+
+impl<R: Read> DecryptReader<R> {
+    pub fn new(inner: R, derived_key: Option<String>) -> EnigmatickResult<Self> {
+        Ok(DecryptReader {
+            inner,
+            decryptor: StreamDecryptor::new(derived_key)?,
+            pending: vec![],
+            position: 0,
+            done: false,
+        })
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut input = vec![0u8; STREAM_CHUNK_SIZE];
+
+        while self.position == self.pending.len() && !self.done {
+            let count = self.inner.read(&mut input)?;
+
+            self.pending = if count == 0 {
+                self.done = true;
+                self.decryptor.finish().map_err(io_error)?
+            } else {
+                self.decryptor.push(&input[..count]).map_err(io_error)?
+            };
+            self.position = 0;
+        }
+
+        let count = (self.pending.len() - self.position).min(buf.len());
+        buf[..count].copy_from_slice(&self.pending[self.position..self.position + count]);
+        self.position += count;
+
+        Ok(count)
+    }
+}
+
+pub fn encrypt_stream<R: Read, W: Write>(
+    derived_key: Option<String>,
+    mut reader: R,
+    writer: W,
+) -> EnigmatickResult<W> {
+    let mut writer = EncryptWriter::new(writer, derived_key)?;
+    io::copy(&mut reader, &mut writer)?;
+    writer.finish()
+}
+
+pub fn decrypt_stream<R: Read, W: Write>(
+    derived_key: Option<String>,
+    reader: R,
+    mut writer: W,
+) -> EnigmatickResult<W> {
+    let mut reader = DecryptReader::new(reader, derived_key)?;
+    io::copy(&mut reader, &mut writer)?;
+    writer.flush()?;
+
+    Ok(writer)
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use super::{StreamDecryptor, StreamEncryptor};
+    use crate::EnigmatickError;
+    use js_sys::Uint8Array;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen::JsCast;
+    use web_sys::{
+        ReadableStream, ReadableWritablePair, TransformStream, TransformStreamDefaultController,
+        Transformer,
+    };
+
+    // StreamEncryptor and StreamDecryptor share this shape
+    trait Chunked: 'static {
+        fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, EnigmatickError>;
+        fn finish(&mut self) -> Result<Vec<u8>, EnigmatickError>;
+    }
+
+    impl Chunked for StreamEncryptor {
+        fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, EnigmatickError> {
+            StreamEncryptor::push(self, data)
+        }
+
+        fn finish(&mut self) -> Result<Vec<u8>, EnigmatickError> {
+            StreamEncryptor::finish(self)
+        }
+    }
+
+    impl Chunked for StreamDecryptor {
+        fn push(&mut self, data: &[u8]) -> Result<Vec<u8>, EnigmatickError> {
+            StreamDecryptor::push(self, data)
+        }
+
+        fn finish(&mut self) -> Result<Vec<u8>, EnigmatickError> {
+            StreamDecryptor::finish(self)
+        }
+    }
+
+    fn enqueue(
+        controller: &TransformStreamDefaultController,
+        output: Result<Vec<u8>, EnigmatickError>,
+    ) -> Result<(), JsValue> {
+        let output = output?;
+
+        if output.is_empty() {
+            return Ok(());
+        }
+
+        controller.enqueue_with_chunk(&Uint8Array::from(output.as_slice()))
+    }
+
+    // A TransformStream from Uint8Array chunks to Uint8Array chunks; errors
+    // reject the write, which errors both sides of the stream
+    fn transform_stream<T: Chunked>(chunked: T) -> Result<TransformStream, EnigmatickError> {
+        let chunked = Rc::new(RefCell::new(chunked));
+
+        let transform = {
+            let chunked = chunked.clone();
+            Closure::<dyn FnMut(JsValue, TransformStreamDefaultController) -> Result<(), JsValue>>::new(
+                move |chunk: JsValue, controller: TransformStreamDefaultController| {
+                    let data = Uint8Array::new(&chunk).to_vec();
+                    enqueue(&controller, chunked.borrow_mut().push(&data))
+                },
+            )
+        };
+
+        let flush =
+            Closure::<dyn FnMut(TransformStreamDefaultController) -> Result<(), JsValue>>::new(
+                move |controller: TransformStreamDefaultController| {
+                    enqueue(&controller, chunked.borrow_mut().finish())
+                },
+            );
+
+        let transformer = Transformer::new();
+        transformer.set_transform(transform.into_js_value().unchecked_ref());
+        transformer.set_flush(flush.into_js_value().unchecked_ref());
+
+        TransformStream::new_with_transformer(&transformer).map_err(|e| {
+            EnigmatickError::Internal(format!("failed to create TransformStream: {e:?}"))
+        })
+    }
+
+    fn pipe_through(stream: &ReadableStream, transform: TransformStream) -> ReadableStream {
+        stream.pipe_through(&ReadableWritablePair::new(
+            &transform.readable(),
+            &transform.writable(),
+        ))
+    }
+
+    #[wasm_bindgen]
+    pub fn encrypt_transform_stream(
+        derived_key: Option<String>,
+    ) -> Result<TransformStream, EnigmatickError> {
+        transform_stream(StreamEncryptor::new(derived_key)?)
+    }
+
+    #[wasm_bindgen]
+    pub fn decrypt_transform_stream(
+        derived_key: Option<String>,
+    ) -> Result<TransformStream, EnigmatickError> {
+        transform_stream(StreamDecryptor::new(derived_key)?)
+    }
+
+    // e.g., encrypt_readable_stream(file.stream()) for an upload body
+    #[wasm_bindgen]
+    pub fn encrypt_readable_stream(
+        stream: ReadableStream,
+        derived_key: Option<String>,
+    ) -> Result<ReadableStream, EnigmatickError> {
+        Ok(pipe_through(
+            &stream,
+            encrypt_transform_stream(derived_key)?,
+        ))
+    }
+
+    #[wasm_bindgen]
+    pub fn decrypt_readable_stream(
+        stream: ReadableStream,
+        derived_key: Option<String>,
+    ) -> Result<ReadableStream, EnigmatickError> {
+        Ok(pipe_through(
+            &stream,
+            decrypt_transform_stream(derived_key)?,
+        ))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub use web::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose, engine::Engine as _};
+
+    fn key() -> String {
+        general_purpose::STANDARD.encode(SecretKey::default().unprotected_as_bytes())
+    }
+
+    fn data(length: usize) -> Vec<u8> {
+        (0..length).map(|x| (x % 251) as u8).collect()
+    }
+
+    fn seal(key: &str, data: &[u8], piece: usize) -> Vec<u8> {
+        let mut encryptor = StreamEncryptor::new(Some(key.to_string())).unwrap();
+        let mut sealed = vec![];
+        for chunk in data.chunks(piece) {
+            sealed.extend(encryptor.push(chunk).unwrap());
+        }
+        sealed.extend(encryptor.finish().unwrap());
+        sealed
+    }
+
+    fn open(key: &str, sealed: &[u8], piece: usize) -> EnigmatickResult<Vec<u8>> {
+        let mut decryptor = StreamDecryptor::new(Some(key.to_string()))?;
+        let mut data = vec![];
+        for chunk in sealed.chunks(piece) {
+            data.extend(decryptor.push(chunk)?);
+        }
+        data.extend(decryptor.finish()?);
+        Ok(data)
+    }
+
+    // the offset of sealed chunk n
+    fn chunk_offset(n: usize) -> usize {
+        HEADER_BYTES + n * (STREAM_CHUNK_SIZE + ABYTES)
+    }
+
+    #[test]
+    fn round_trips_at_chunk_boundaries() {
+        let key = key();
+
+        for length in [
+            0,
+            1,
+            STREAM_CHUNK_SIZE - 1,
+            STREAM_CHUNK_SIZE,
+            STREAM_CHUNK_SIZE + 1,
+            3 * STREAM_CHUNK_SIZE + 17,
+        ] {
+            let data = data(length);
+
+            // pieces that straddle the chunk boundaries on both sides
+            let sealed = seal(&key, &data, 1000);
+            assert_eq!(open(&key, &sealed, 777).unwrap(), data, "length {length}");
+            assert_eq!(open(&key, &sealed, sealed.len().max(1)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn round_trips_through_reader_and_writer() {
+        let key = key();
+        let data = data(2 * STREAM_CHUNK_SIZE + 5);
+
+        let sealed = encrypt_stream(Some(key.clone()), data.as_slice(), vec![]).unwrap();
+        let opened = decrypt_stream(Some(key), sealed.as_slice(), vec![]).unwrap();
+
+        assert_eq!(opened, data);
+    }
+
+    #[test]
+    fn refuses_truncated_streams() {
+        let key = key();
+        let sealed = seal(&key, &data(2 * STREAM_CHUNK_SIZE + 10), 4096);
+
+        // without the final chunk, at a chunk boundary
+        assert!(open(&key, &sealed[..chunk_offset(2)], 4096).is_err());
+        // partway through a chunk
+        assert!(open(&key, &sealed[..chunk_offset(1) + 100], 4096).is_err());
+        // the header alone
+        assert!(open(&key, &sealed[..HEADER_BYTES], 4096).is_err());
+
+        let mut reader = DecryptReader::new(&sealed[..chunk_offset(2)], Some(key)).unwrap();
+        assert!(io::copy(&mut reader, &mut io::sink()).is_err());
+    }
+
+    #[test]
+    fn refuses_reordered_and_altered_streams() {
+        let key = key();
+        let sealed = seal(&key, &data(2 * STREAM_CHUNK_SIZE + 10), 4096);
+
+        let mut reordered = sealed[..HEADER_BYTES].to_vec();
+        reordered.extend(&sealed[chunk_offset(1)..chunk_offset(2)]);
+        reordered.extend(&sealed[chunk_offset(0)..chunk_offset(1)]);
+        reordered.extend(&sealed[chunk_offset(2)..]);
+        assert!(open(&key, &reordered, 4096).is_err());
+
+        let mut altered = sealed.clone();
+        altered[chunk_offset(1) + 10] ^= 1;
+        assert!(open(&key, &altered, 4096).is_err());
+
+        let mut extended = sealed.clone();
+        extended.push(0);
+        assert!(open(&key, &extended, 4096).is_err());
+
+        assert!(open(&self::key(), &sealed, 4096).is_err());
+    }
+
+    #[test]
+    fn exposes_the_nonce() {
+        let sealed = seal(&key(), b"data", 4);
+
+        assert_eq!(
+            stream_nonce(&sealed),
+            Some(&sealed[MAGIC.len() + 4..HEADER_BYTES])
+        );
+        assert_eq!(stream_nonce(b"EKS0"), None);
+    }
+}
@@ -1,26 +1,36 @@
+use crate::{get_conversation, get_key, log, send_get};
 use crate::{
     get_hash, get_mls_keys, get_remote_keys, get_state, NoteParams, DECRYPT_FN, ENCRYPT_FN, HASH_FN,
 };
-use crate::{get_key, log, send_get};
 use anyhow::{anyhow, Result};
 use base64::engine::{general_purpose, Engine as _};
 use jdt_activity_pub::session::CredentialKeyPair;
-use jdt_activity_pub::{ActivityPub, ApInstrument, ApInstrumentType, ApObject, Collectible};
+use jdt_activity_pub::{
+    ActivityPub, ApActivity, ApInstrument, ApInstrumentType, ApObject, Collectible, MaybeReference,
+};
 use openmls::group::{GroupId, MlsGroup, MlsGroupCreateConfig};
 use openmls::prelude::{
-    tls_codec::*, CredentialWithKey, KeyPackage, MlsMessageOut, OpenMlsCrypto, OpenMlsProvider,
+    tls_codec::*, BasicCredential, CredentialWithKey, KeyPackage, MlsMessageOut, OpenMlsCrypto,
+    OpenMlsProvider,
 };
 use openmls_rust_crypto::OpenMlsRustCrypto;
+use std::collections::HashSet;
+
+pub type StoredCredentials = (CredentialKeyPair, OpenMlsRustCrypto, Option<String>);
+
+pub async fn retrieve_credentials() -> Result<StoredCredentials> {
+    stored_credentials()
+        .await?
+        .ok_or(anyhow!("No MLS credentials"))
+}
 
-pub async fn retrieve_credentials() -> Result<(CredentialKeyPair, OpenMlsRustCrypto, Option<String>)>
-{
+// None only when the account has no MLS credentials or storage yet
+pub async fn stored_credentials() -> Result<Option<StoredCredentials>> {
     let activity_pubs = get_mls_keys()
         .await
-        .ok_or(anyhow!(
-            "Failed to retrieve user MLS credentials and storage"
-        ))?
+        .map_err(|e| anyhow!("Failed to retrieve user MLS credentials and storage: {e}"))?
         .items()
-        .ok_or(anyhow!("No items"))?;
+        .unwrap_or_default();
 
     let instruments: Vec<ApInstrument> = activity_pubs
         .iter()
@@ -35,28 +45,42 @@ pub async fn retrieve_credentials() -> Result<(CredentialKeyPair, OpenMlsRustCry
 
     let credentials = instruments
         .iter()
-        .find(|instrument| instrument.is_mls_credentials())
-        .ok_or(anyhow!("Instrument must be Some"))?
-        .to_credentials(DECRYPT_FN)?;
-
+        .find(|instrument| instrument.is_mls_credentials());
     let provider = instruments
         .iter()
-        .find(|instrument| instrument.is_mls_storage())
-        .ok_or(anyhow!("Instrument must be Some"))?;
+        .find(|instrument| instrument.is_mls_storage());
+
+    let (credentials, provider) = match (credentials, provider) {
+        (Some(credentials), Some(provider)) => (credentials, provider),
+        (None, None) => return Ok(None),
+        _ => return Err(anyhow!("MLS credentials and storage must both be present")),
+    };
 
     let mutation_of = provider.hash.clone();
-    let provider = provider.to_provider(DECRYPT_FN)?;
 
-    Ok((credentials, provider, mutation_of))
+    Ok(Some((
+        credentials.to_credentials(DECRYPT_FN)?,
+        provider.to_provider(DECRYPT_FN)?,
+        mutation_of,
+    )))
 }
 
 pub async fn create_mls_group(params: &mut NoteParams) -> Result<()> {
     let (credentials, provider, mutation_of) = retrieve_credentials().await?;
+    create_group_with(params, &credentials, &provider, mutation_of).await
+}
 
+async fn create_group_with(
+    params: &mut NoteParams,
+    credentials: &CredentialKeyPair,
+    provider: &OpenMlsRustCrypto,
+    mutation_of: Option<String>,
+) -> Result<()> {
     let mut key_packages: Vec<KeyPackage> = vec![];
     for webfinger in params.mentions.keys() {
         if let Some(keys) = get_remote_keys(webfinger.clone())
             .await
+            .ok()
             .and_then(|x| x.items())
         {
             key_packages.extend(keys.into_iter().filter_map(|ap| match ap {
@@ -72,27 +96,117 @@ pub async fn create_mls_group(params: &mut NoteParams) -> Result<()> {
     let group_config = group_config_builder.build();
 
     let mut group = MlsGroup::new(
-        &provider,
+        provider,
         &credentials.key_pair,
         &group_config,
         credentials.credential_with_key.clone(),
     )?;
 
     let (_commit, welcome_out, _group_info) =
-        group.add_members(&provider, &credentials.key_pair, &key_packages)?;
+        group.add_members(provider, &credentials.key_pair, &key_packages)?;
 
-    group.merge_pending_commit(&provider)?;
+    group.merge_pending_commit(provider)?;
 
-    params.add_instrument(ApInstrument::try_from((
-        params.get_content().clone(),
-        ENCRYPT_FN,
-    ))?);
+    seal_message(params, &mut group, provider, credentials, mutation_of)?;
+    //params.add_instrument(ApInstrument::from((credentials, ENCRYPT_FN)));
+    params.add_instrument(welcome_out.try_into()?);
+    params.add_instrument(group.group_id().clone().into());
 
-    let encrypted = group.create_message(
-        &provider,
-        &credentials.key_pair,
-        params.get_content().as_bytes(),
-    )?;
+    Ok(())
+}
+
+// Sends a follow-up message through the group already used in the
+// conversation, so the recipients need no Welcome and the group's ratchet
+// carries on. A new group is created if none of the conversation's notes
+// names one that is in our storage, or if its members are not exactly the
+// note's recipients (someone was added to or dropped from the conversation).
+pub async fn use_mls_group(params: &mut NoteParams, conversation: String) -> Result<()> {
+    let (credentials, provider, mutation_of) = retrieve_credentials().await?;
+
+    let group = find_conversation_group(&conversation, &provider)
+        .await
+        .filter(|group| has_recipients(group, params));
+
+    let Some(mut group) = group else {
+        log(&format!(
+            "No MlsGroup with these recipients found for {conversation}; creating one"
+        ));
+        return create_group_with(params, &credentials, &provider, mutation_of).await;
+    };
+
+    seal_message(params, &mut group, &provider, &credentials, mutation_of)?;
+    params.add_instrument(group.group_id().clone().into());
+
+    Ok(())
+}
+
+// The group named by a GroupId instrument on a note in the conversation
+// (on our own notes) or on its Create (on notes sent to us)
+async fn find_conversation_group(
+    conversation: &str,
+    provider: &OpenMlsRustCrypto,
+) -> Option<MlsGroup> {
+    let text = get_conversation(conversation.to_string(), 50).await.ok()?;
+
+    let ApObject::Collection(collection) = serde_json::from_str(&text).ok()? else {
+        return None;
+    };
+
+    collection
+        .items()?
+        .into_iter()
+        .flat_map(|item| match item {
+            ActivityPub::Activity(ApActivity::Create(create)) => {
+                let mut instruments = create.instrument.multiple();
+                if let MaybeReference::Actual(ApObject::Note(note)) = create.object {
+                    instruments.extend(note.instrument.multiple());
+                }
+                instruments
+            }
+            ActivityPub::Object(ApObject::Note(note)) => note.instrument.multiple(),
+            _ => vec![],
+        })
+        .filter(|instrument| instrument.is_mls_group_id())
+        .filter_map(|instrument| GroupId::try_from(instrument).ok())
+        .filter_map(|group_id| MlsGroup::load(provider.storage(), &group_id).ok()?)
+        .find(|group| group.is_active())
+}
+
+// Member identities are actor IDs (see CredentialKeyPair); ours is included
+fn has_recipients(group: &MlsGroup, params: &NoteParams) -> bool {
+    let members: HashSet<Vec<u8>> = group
+        .members()
+        .filter_map(|member| BasicCredential::try_from(member.credential).ok())
+        .map(|credential| credential.identity().to_vec())
+        .collect();
+
+    let mut recipients: HashSet<Vec<u8>> = params
+        .mentions
+        .values()
+        .map(|(id, _)| id.as_bytes().to_vec())
+        .collect();
+
+    if let Some(profile) = get_state().profile {
+        recipients.insert(profile.id.to_string().into_bytes());
+    }
+
+    members == recipients
+}
+
+// Replaces the content with the MLS application message and adds the
+// plaintext (as a vault item) and the updated storage as instruments
+fn seal_message(
+    params: &mut NoteParams,
+    group: &mut MlsGroup,
+    provider: &OpenMlsRustCrypto,
+    credentials: &CredentialKeyPair,
+    mutation_of: Option<String>,
+) -> Result<()> {
+    let message = params.get_message()?;
+
+    params.add_instrument(ApInstrument::try_from((message.clone(), ENCRYPT_FN))?);
+
+    let encrypted = group.create_message(provider, &credentials.key_pair, message.as_bytes())?;
 
     let encrypted_serialized = encrypted.tls_serialize_detached().unwrap();
     let encrypted_encoded = general_purpose::STANDARD.encode(encrypted_serialized);
@@ -104,9 +218,6 @@ pub async fn create_mls_group(params: &mut NoteParams) -> Result<()> {
         ENCRYPT_FN,
         HASH_FN,
     )));
-    //params.add_instrument(ApInstrument::from((credentials, ENCRYPT_FN)));
-    params.add_instrument(welcome_out.try_into()?);
-    params.add_instrument(group.group_id().clone().into());
 
     Ok(())
 }
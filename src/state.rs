@@ -1,24 +1,115 @@
 extern crate console_error_panic_hook;
 
 use std::collections::HashMap;
-use std::error::Error;
+use std::fmt;
 
-use base64::{engine::general_purpose, engine::Engine as _};
-use lazy_static::lazy_static;
-use orion::kdf;
-use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use wasm_bindgen::prelude::wasm_bindgen;
+use zeroize::{Zeroize, Zeroizing};
 
-use crate::Profile;
+use crate::{
+    activate_account, begin_account, check_unlocked, current_account, defer_events, derive_key,
+    emit, encode_derived_key, get_account, mark_unlocked, note_activity, register_account, Account,
+    EnigmatickError, EnigmatickEvent, EnigmatickResult, KdfParams, Profile,
+};
 
-lazy_static! {
-    static ref ENIGMATICK_STATE: Arc<Mutex<EnigmatickState>> =
-        Arc::new(Mutex::new(EnigmatickState::new()));
+// The state and secrets of each account are held by an Account (see
+// account.rs). The secrets are kept out of EnigmatickState so that get_state()
+// (which clones) and export() never copy them.
+
+// A String that is wiped from memory when dropped and never printed
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        SecretString(value)
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecretString(..)")
+    }
+}
+
+impl Serialize for SecretString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(SecretString)
+    }
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct StateSecrets {
+    // this is stored because derivation is expensive
+    derived_key: Option<SecretString>,
+
+    // keys derived with the profile's earlier KDF parameters, for data that
+    // was sealed before a migration
+    #[serde(default)]
+    previous_derived_keys: Vec<SecretString>,
+
+    // this is the decrypted, PEM encoded client key from the keystore
+    client_private_key_pem: Option<SecretString>,
+
+    // this is the decrypted, pickled olm account from the keystore
+    olm_pickled_account: Option<SecretString>,
+}
+
+impl StateSecrets {
+    pub fn derived_key(&self) -> EnigmatickResult<&SecretString> {
+        self.derived_key
+            .as_ref()
+            .ok_or(EnigmatickError::missing("derived_key"))
+    }
+
+    pub fn previous_derived_keys(&self) -> &[SecretString] {
+        &self.previous_derived_keys
+    }
+
+    pub fn client_private_key_pem(&self) -> EnigmatickResult<&SecretString> {
+        self.client_private_key_pem
+            .as_ref()
+            .ok_or(EnigmatickError::missing("client_private_key_pem"))
+    }
+
+    pub fn olm_pickled_account(&self) -> EnigmatickResult<&SecretString> {
+        self.olm_pickled_account
+            .as_ref()
+            .ok_or(EnigmatickError::missing("olm_pickled_account"))
+    }
+
+    // The current key becomes the newest previous key
+    pub fn replace_derived_key(&mut self, key: String) {
+        if let Some(old_key) = self.derived_key.replace(key.into()) {
+            self.previous_derived_keys.insert(0, old_key);
+        }
+    }
 }
 
 #[wasm_bindgen(getter_with_clone)]
 #[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct EnigmatickState {
     // e.g., enigmatick.jdt.dev or 192.168.1.1:8080
     // pulled from /api/v2/instance
@@ -31,9 +122,6 @@ pub struct EnigmatickState {
     // self-explanatory
     pub authenticated: bool,
 
-    // this is stored in state because derivation is expensive
-    pub derived_key: Option<String>,
-
     // the keystore is in the profile, but it's stringified
     pub profile: Option<Profile>,
 
@@ -43,11 +131,7 @@ pub struct EnigmatickState {
     // #[wasm_bindgen(skip)]
     // pub keystore: Option<KeyStore>,
 
-    // this is the decrypted, PEM encoded client key from the keystore
-    pub client_private_key_pem: Option<String>,
-
-    // this is the decrypted, pickled olm account from the keystore
-    olm_pickled_account: Option<String>,
+    // the derived key, client key and olm account are in StateSecrets
 
     // this is the decrypted map of user identities to pickled sessions decrypted
     // and decoded from the keystore
@@ -60,10 +144,7 @@ impl Default for EnigmatickState {
             server_name: None,
             server_url: None,
             authenticated: false,
-            derived_key: None,
             profile: None,
-            client_private_key_pem: None,
-            olm_pickled_account: None,
             olm_sessions: None,
         }
     }
@@ -96,14 +177,11 @@ impl EnigmatickState {
     }
 
     pub fn set_derived_key(&mut self, key: String) -> Self {
-        self.derived_key = Some(key);
+        set_secret(|secrets| secrets.derived_key = Some(key.into()));
+        mark_unlocked();
         self.clone()
     }
 
-    pub fn get_derived_key(&self) -> Option<String> {
-        self.derived_key.clone()
-    }
-
     pub fn set_profile(&mut self, profile: Profile) -> Self {
         self.profile = Some(profile);
         self.clone()
@@ -114,23 +192,15 @@ impl EnigmatickState {
     }
 
     pub fn set_client_private_key_pem(&mut self, pem: String) -> Self {
-        self.client_private_key_pem = Some(pem);
+        set_secret(|secrets| secrets.client_private_key_pem = Some(pem.into()));
         self.clone()
     }
 
-    pub fn get_client_private_key_pem(&self) -> Option<String> {
-        self.client_private_key_pem.clone()
-    }
-
     pub fn set_olm_pickled_account(&mut self, olm_pickled_account: String) -> Self {
-        self.olm_pickled_account = Some(olm_pickled_account);
+        set_secret(|secrets| secrets.olm_pickled_account = Some(olm_pickled_account.into()));
         self.clone()
     }
 
-    pub fn get_olm_pickled_account(&self) -> Option<String> {
-        self.olm_pickled_account.clone()
-    }
-
     pub fn set_olm_sessions(&mut self, olm_sessions: String) -> Self {
         self.olm_sessions =
             Some(serde_json::from_str::<HashMap<String, String>>(&olm_sessions).unwrap());
@@ -158,70 +228,249 @@ impl EnigmatickState {
         self.authenticated
     }
 
+    // Secrets are not included; see export_with_secrets
     pub fn export(&self) -> String {
         serde_json::to_string(self).unwrap()
     }
+
+    // The state along with the derived key, client key and olm account, in
+    // cleartext. Only for handing the session to another context that is
+    // just as trusted (e.g., a worker); never for storage.
+    pub fn export_with_secrets(&self) -> Result<String, EnigmatickError> {
+        #[derive(Serialize)]
+        struct Exported<'a> {
+            #[serde(flatten)]
+            state: &'a EnigmatickState,
+            #[serde(flatten)]
+            secrets: &'a StateSecrets,
+        }
+
+        with_secrets(|secrets| {
+            Ok(serde_json::to_string(&Exported {
+                state: self,
+                secrets,
+            })?)
+        })
+    }
+}
+
+impl EnigmatickState {
+    pub fn set_previous_derived_keys(&mut self, keys: Vec<String>) -> Self {
+        set_secret(|secrets| {
+            secrets.previous_derived_keys = keys.into_iter().map(SecretString::from).collect()
+        });
+        self.clone()
+    }
 }
 
+// The setters above return Self for the JS builder style, so a poisoned lock
+// cannot be reported from them
+fn set_secret<F: FnOnce(&mut StateSecrets)>(set_fn: F) {
+    if let Ok(mut secrets) = current_account().secrets.lock() {
+        set_fn(&mut secrets);
+    }
+}
+
+// Borrows the secrets under their lock. Nothing is copied unless f copies it,
+// so f should do its work (decoding a key, signing, sealing) in place. f must
+// not call with_secrets or update_secrets itself. Fails with Locked while the
+// session is locked.
+pub fn with_secrets<F, T>(read_fn: F) -> EnigmatickResult<T>
+where
+    F: FnOnce(&StateSecrets) -> EnigmatickResult<T>,
+{
+    check_unlocked()?;
+
+    let account = current_account();
+    let secrets = account
+        .secrets
+        .lock()
+        .map_err(|e| EnigmatickError::Internal(e.to_string()))?;
+    read_fn(&secrets)
+}
+
+pub fn update_secrets<F>(update_fn: F) -> EnigmatickResult<()>
+where
+    F: FnOnce(&mut StateSecrets) -> EnigmatickResult<()>,
+{
+    check_unlocked()?;
+
+    let account = current_account();
+    let mut secrets = account
+        .secrets
+        .lock()
+        .map_err(|e| EnigmatickError::Internal(e.to_string()))?;
+    update_fn(&mut secrets)
+}
+
+// Restores the output of export() or export_with_secrets() into the account
+// it belongs to (or a new one), which becomes active. Anything missing from
+// the data (e.g., in an export from an older version) is left as it is.
 #[wasm_bindgen]
-pub fn import_state(data: String) {
-    let imported_state: EnigmatickState = serde_json::from_str(&data).unwrap();
+pub fn import_state(data: String) -> Result<(), EnigmatickError> {
+    #[derive(Deserialize)]
+    struct Secrets {
+        derived_key: Option<serde::de::IgnoredAny>,
+    }
 
-    let state = &*ENIGMATICK_STATE.clone();
+    let data = Zeroizing::new(data);
+    let imported_state: EnigmatickState = serde_json::from_str(&data)?;
+    // present only in the output of export_with_secrets (or of an older
+    // export(), which always included them); a malformed section is an error
+    // rather than an import without secrets
+    let imported_secrets: StateSecrets = match serde_json::from_str::<Secrets>(&data)?.derived_key {
+        Some(_) => serde_json::from_str(&data)?,
+        None => StateSecrets::default(),
+    };
+
+    let account = imported_state
+        .profile
+        .as_ref()
+        .and_then(|x| get_account(&x.id.to_string()))
+        .unwrap_or_else(begin_account);
+    activate_account(account.clone());
 
-    if let Ok(mut x) = state.try_lock() {
-        x.set_derived_key(imported_state.derived_key.unwrap());
+    update_account_state(&account, |x| {
         x.authenticated = imported_state.authenticated;
-        x.set_client_private_key_pem(imported_state.client_private_key_pem.unwrap());
-        x.set_profile(imported_state.profile.unwrap());
-        x.set_olm_pickled_account(imported_state.olm_pickled_account.unwrap());
-        // x.set_keystore(imported_state.keystore.unwrap());
-    };
+        if let Some(profile) = imported_state.profile {
+            x.set_profile(profile);
+        }
+        if imported_state.server_name.is_some() {
+            x.server_name = imported_state.server_name;
+        }
+        if imported_state.server_url.is_some() {
+            x.server_url = imported_state.server_url;
+        }
+        if imported_state.olm_sessions.is_some() {
+            x.olm_sessions = imported_state.olm_sessions;
+        }
+        Ok(())
+    })?;
+
+    if imported_secrets.derived_key.is_some() {
+        if let Ok(mut secrets) = account.secrets.lock() {
+            *secrets = imported_secrets;
+        }
+        account.locked.store(false, Ordering::SeqCst);
+        note_activity();
+        emit(EnigmatickEvent::Unlocked {
+            actor: account.id(),
+        });
+    }
+
+    Ok(())
 }
 
-#[wasm_bindgen]
 pub fn get_state() -> EnigmatickState {
-    let state = &*ENIGMATICK_STATE;
+    current_account()
+        .state
+        .lock()
+        .map(|x| x.clone())
+        .unwrap_or_default()
+}
 
-    if let Ok(x) = state.lock() {
-        x.clone()
-    } else {
-        EnigmatickState::default()
-    }
+pub fn update_state<F>(update_fn: F) -> EnigmatickResult<()>
+where
+    F: FnOnce(&mut EnigmatickState) -> EnigmatickResult<()>,
+{
+    update_account_state(&current_account(), update_fn)
 }
 
-pub fn update_state<F>(update_fn: F) -> Result<String, Box<dyn Error>>
+fn update_account_state<F>(account: &Arc<Account>, update_fn: F) -> EnigmatickResult<()>
 where
-    F: FnOnce(&mut EnigmatickState) -> Result<(), Box<dyn Error>>,
+    F: FnOnce(&mut EnigmatickState) -> EnigmatickResult<()>,
 {
-    let state = &*ENIGMATICK_STATE;
+    defer_events(|| {
+        let (result, events) = {
+            let mut state = account
+                .state
+                .lock()
+                .map_err(|e| EnigmatickError::Internal(e.to_string()))?;
+
+            let before = Snapshot::of(account, &state);
+            let result = update_fn(&mut state);
+            (result, before.changes(&Snapshot::of(account, &state)))
+        };
+
+        // the state lock has to be released first
+        register_account(account);
+        events.into_iter().for_each(emit);
+        result
+    })
+}
+
+// What update_account_state compares to decide which events to raise
+struct Snapshot {
+    authenticated: bool,
+    actor: Option<String>,
+    profile: Option<String>,
+    unlocked: bool,
+}
+
+impl Snapshot {
+    fn of(account: &Account, state: &EnigmatickState) -> Self {
+        Snapshot {
+            authenticated: state.authenticated,
+            actor: state.profile.as_ref().map(|x| x.id.to_string()),
+            profile: state
+                .profile
+                .as_ref()
+                .and_then(|x| serde_json::to_string(x).ok()),
+            unlocked: account
+                .secrets
+                .lock()
+                .is_ok_and(|x| x.derived_key.is_some()),
+        }
+    }
+
+    fn changes(&self, after: &Snapshot) -> Vec<EnigmatickEvent> {
+        let mut events = vec![];
+
+        match (self.authenticated, after.authenticated, &after.actor) {
+            (false, true, Some(actor)) => events.push(EnigmatickEvent::Authenticated {
+                actor: actor.clone(),
+            }),
+            (true, true, Some(actor)) if self.profile != after.profile => {
+                events.push(EnigmatickEvent::ProfileUpdated {
+                    actor: actor.clone(),
+                })
+            }
+            (true, false, _) => {
+                if let Some(actor) = self.actor.clone() {
+                    events.push(EnigmatickEvent::LoggedOut { actor })
+                }
+            }
+            _ => {}
+        }
 
-    let mut state = state.lock().map_err(|e| e.to_string())?;
-    update_fn(&mut state)?;
+        if !self.unlocked && after.unlocked {
+            events.push(EnigmatickEvent::Unlocked {
+                actor: after.actor.clone(),
+            });
+        }
 
-    Ok(true.to_string())
+        events
+    }
 }
 
-pub fn update_state_password(password: String) -> Result<String, Box<dyn Error>> {
+// Re-derives the key after a password change; earlier keys cannot be derived
+// from the new password, so they are dropped along with the old one
+pub fn update_state_password(password: String, params: KdfParams) -> EnigmatickResult<()> {
     update_state(|state| {
-        let salt = kdf::Salt::from_slice(
-            &general_purpose::STANDARD.decode(
-                state
-                    .profile
-                    .as_ref()
-                    .ok_or("Missing profile")?
-                    .salt
-                    .as_ref()
-                    .ok_or("Missing salt")?,
-            )?,
-        )?;
-        let password = kdf::Password::from_slice(password.as_bytes())?;
-
-        if let Ok(derived_key) = kdf::derive_key(&password, &salt, 3, 1 << 4, 32) {
-            let encoded_derived_key =
-                general_purpose::STANDARD.encode(derived_key.unprotected_as_bytes());
-            state.set_derived_key(encoded_derived_key);
-        }
+        let profile = state
+            .profile
+            .as_mut()
+            .ok_or(EnigmatickError::missing("profile"))?;
+        let salt = profile
+            .salt
+            .clone()
+            .ok_or(EnigmatickError::missing("salt"))?;
+
+        let derived_key = derive_key(password, salt, &params)?;
+        profile.kdf = Some(params);
+
+        state.set_derived_key(encode_derived_key(&derived_key));
+        state.set_previous_derived_keys(vec![]);
 
         Ok(())
     })
@@ -0,0 +1,352 @@
+use base64::{engine::general_purpose, engine::Engine as _};
+use futures::future::LocalBoxFuture;
+use lazy_static::lazy_static;
+use orion::aead::SecretKey;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::wasm_bindgen;
+use zeroize::Zeroizing;
+
+use crate::{
+    decrypt, decrypt_bytes, derive_key, encode_derived_key, encrypt, encrypt_bytes, get_state,
+    import_state, with_secrets, EnigmatickError, EnigmatickResult, KdfParams,
+};
+
+// Persists the session under a device key, itself sealed under the password-derived key
+
+lazy_static! {
+    static ref STATE_STORE: Mutex<Arc<dyn StateStore>> = Mutex::new(default_state_store());
+}
+
+// Bump when PersistedState changes and add the migration to parse_persisted
+const STATE_SCHEMA_VERSION: u32 = 1;
+
+const STATE_KEY: &str = "session";
+
+// Where the persisted session is kept. The default is IndexedDB in the
+// browser and a file under storage_home() natively.
+pub trait StateStore: Send + Sync {
+    fn load<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, EnigmatickResult<Option<String>>>;
+    fn save<'a>(&'a self, key: &'a str, value: &'a str)
+        -> LocalBoxFuture<'a, EnigmatickResult<()>>;
+    fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, EnigmatickResult<()>>;
+}
+
+pub fn set_state_store(store: Arc<dyn StateStore>) {
+    if let Ok(mut x) = STATE_STORE.lock() {
+        *x = store;
+    }
+}
+
+pub fn get_state_store() -> Arc<dyn StateStore> {
+    STATE_STORE
+        .lock()
+        .map(|x| x.clone())
+        .unwrap_or_else(|_| default_state_store())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn default_state_store() -> Arc<dyn StateStore> {
+    Arc::new(IndexedDbStateStore)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_state_store() -> Arc<dyn StateStore> {
+    Arc::new(FileStateStore::default())
+}
+
+// Shares the IndexedDB object store used by storage.rs
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Debug, Default)]
+pub struct IndexedDbStateStore;
+
+#[cfg(target_arch = "wasm32")]
+impl StateStore for IndexedDbStateStore {
+    fn load<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, EnigmatickResult<Option<String>>> {
+        Box::pin(crate::storage_load(key))
+    }
+
+    fn save<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+    ) -> LocalBoxFuture<'a, EnigmatickResult<()>> {
+        Box::pin(crate::storage_save(key, value))
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, EnigmatickResult<()>> {
+        Box::pin(crate::storage_remove(key))
+    }
+}
+
+// localStorage is synchronous and survives where IndexedDB is unavailable
+// (e.g., some private browsing modes), but it only exists in a Window
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Debug, Default)]
+pub struct LocalStorageStateStore;
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorageStateStore {
+    fn storage() -> EnigmatickResult<web_sys::Storage> {
+        web_sys::window()
+            .ok_or(EnigmatickError::Internal(
+                "localStorage is only available in a Window".to_string(),
+            ))?
+            .local_storage()
+            .map_err(crate::storage::storage_error)?
+            .ok_or(EnigmatickError::missing("localStorage"))
+    }
+
+    fn item(key: &str) -> String {
+        format!("enigmatick.{key}")
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl StateStore for LocalStorageStateStore {
+    fn load<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, EnigmatickResult<Option<String>>> {
+        Box::pin(async move {
+            LocalStorageStateStore::storage()?
+                .get_item(&LocalStorageStateStore::item(key))
+                .map_err(crate::storage::storage_error)
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+    ) -> LocalBoxFuture<'a, EnigmatickResult<()>> {
+        Box::pin(async move {
+            LocalStorageStateStore::storage()?
+                .set_item(&LocalStorageStateStore::item(key), value)
+                .map_err(crate::storage::storage_error)
+        })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, EnigmatickResult<()>> {
+        Box::pin(async move {
+            LocalStorageStateStore::storage()?
+                .remove_item(&LocalStorageStateStore::item(key))
+                .map_err(crate::storage::storage_error)
+        })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub struct FileStateStore {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for FileStateStore {
+    fn default() -> Self {
+        FileStateStore {
+            dir: crate::storage_home(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileStateStore {
+    pub fn new(dir: std::path::PathBuf) -> Self {
+        FileStateStore { dir }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl StateStore for FileStateStore {
+    fn load<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, EnigmatickResult<Option<String>>> {
+        Box::pin(async move { crate::storage::load_in(&self.dir, key) })
+    }
+
+    fn save<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+    ) -> LocalBoxFuture<'a, EnigmatickResult<()>> {
+        Box::pin(async move { crate::storage::save_in(&self.dir, key, value) })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, EnigmatickResult<()>> {
+        Box::pin(async move { crate::storage::remove_in(&self.dir, key) })
+    }
+}
+
+// Nothing survives the process; for tests and tools
+#[derive(Debug, Default)]
+pub struct MemoryStateStore {
+    values: Mutex<HashMap<String, String>>,
+}
+
+impl MemoryStateStore {
+    pub fn new() -> Self {
+        MemoryStateStore::default()
+    }
+}
+
+impl StateStore for MemoryStateStore {
+    fn load<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, EnigmatickResult<Option<String>>> {
+        Box::pin(async move {
+            Ok(self
+                .values
+                .lock()
+                .map_err(|e| EnigmatickError::Internal(e.to_string()))?
+                .get(key)
+                .cloned())
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        key: &'a str,
+        value: &'a str,
+    ) -> LocalBoxFuture<'a, EnigmatickResult<()>> {
+        Box::pin(async move {
+            self.values
+                .lock()
+                .map_err(|e| EnigmatickError::Internal(e.to_string()))?
+                .insert(key.to_string(), value.to_string());
+            Ok(())
+        })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, EnigmatickResult<()>> {
+        Box::pin(async move {
+            self.values
+                .lock()
+                .map_err(|e| EnigmatickError::Internal(e.to_string()))?
+                .remove(key);
+            Ok(())
+        })
+    }
+}
+
+// Chooses the store from JavaScript: "indexeddb", "localstorage" or "memory"
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn select_state_store(backend: String) -> Result<(), EnigmatickError> {
+    let store: Arc<dyn StateStore> = match backend.to_lowercase().as_str() {
+        "indexeddb" => Arc::new(IndexedDbStateStore),
+        "localstorage" => Arc::new(LocalStorageStateStore),
+        "memory" => Arc::new(MemoryStateStore::new()),
+        _ => {
+            return Err(EnigmatickError::InvalidInput(format!(
+                "unknown state store: {backend}"
+            )))
+        }
+    };
+
+    set_state_store(store);
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct PersistedState {
+    version: u32,
+    username: String,
+    salt: String,
+    kdf: KdfParams,
+    // the device key, sealed under the derived key
+    device_key: String,
+    // export_with_secrets(), sealed under the device key
+    state: String,
+}
+
+fn parse_persisted(data: &str) -> EnigmatickResult<PersistedState> {
+    let value: Value = serde_json::from_str(data)?;
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+
+    match version {
+        x if x == STATE_SCHEMA_VERSION as u64 => Ok(serde_json::from_value(value)?),
+        x => Err(EnigmatickError::InvalidInput(format!(
+            "saved state has unsupported schema version {x}"
+        ))),
+    }
+}
+
+// Saves the session of the active account (replacing any saved session of
+// another account); a fresh device key is used for each save. The
+// saved copy is not updated by later changes (e.g., a password change), so
+// this should be called again after them.
+pub async fn persist_state() -> Result<(), EnigmatickError> {
+    let state = get_state();
+    let profile = state
+        .profile
+        .clone()
+        .ok_or(EnigmatickError::NotAuthenticated)?;
+
+    let exported = Zeroizing::new(state.export_with_secrets()?);
+    let device_key = Zeroizing::new(
+        general_purpose::STANDARD.encode(SecretKey::default().unprotected_as_bytes()),
+    );
+
+    let persisted = PersistedState {
+        version: STATE_SCHEMA_VERSION,
+        username: profile.username.clone(),
+        salt: profile
+            .salt
+            .clone()
+            .ok_or(EnigmatickError::missing("salt"))?,
+        kdf: KdfParams::of(&profile),
+        device_key: with_secrets(|secrets| {
+            encrypt(
+                Some(secrets.derived_key()?.expose().to_string()),
+                device_key.to_string(),
+            )
+        })?,
+        state: general_purpose::STANDARD.encode(encrypt_bytes(
+            Some(device_key.to_string()),
+            exported.as_bytes(),
+        )?),
+    };
+
+    get_state_store()
+        .save(STATE_KEY, &serde_json::to_string(&persisted)?)
+        .await
+}
+
+// The account whose session is saved, if any, so that the UI can ask for the
+// right password after a reload
+pub async fn persisted_username() -> Result<Option<String>, EnigmatickError> {
+    match get_state_store().load(STATE_KEY).await? {
+        Some(data) => Ok(Some(parse_persisted(&data)?.username)),
+        None => Ok(None),
+    }
+}
+
+// Restores the saved session with the password; returns false if there is
+// none. An incorrect password fails with Decryption.
+pub async fn restore_state(password: String) -> Result<bool, EnigmatickError> {
+    let Some(data) = get_state_store().load(STATE_KEY).await? else {
+        return Ok(false);
+    };
+
+    let persisted = parse_persisted(&data)?;
+
+    let derived_key = encode_derived_key(&derive_key(
+        password,
+        persisted.salt.clone(),
+        &persisted.kdf,
+    )?);
+    let device_key = Zeroizing::new(
+        decrypt(Some(derived_key), persisted.device_key)
+            .map_err(|_| EnigmatickError::Decryption("incorrect password".to_string()))?,
+    );
+
+    let state = Zeroizing::new(String::from_utf8(decrypt_bytes(
+        Some(device_key.to_string()),
+        persisted.state,
+    )?)?);
+
+    import_state(state.to_string())?;
+    Ok(true)
+}
+
+pub async fn clear_persisted_state() -> Result<(), EnigmatickError> {
+    get_state_store().remove(STATE_KEY).await
+}
@@ -0,0 +1,228 @@
+use crate::{EnigmatickError, EnigmatickResult};
+
+// Small persistent key/value storage for data that must survive a reload
+// (e.g., the offline outbox queue). Values are strings; callers serialize.
+// In the browser this is an IndexedDB object store; natively it is one file
+// per key under $ENIGMATICK_HOME (or ~/.enigmatick), or the directory given
+// to Client::with_storage_dir.
+
+pub(crate) fn storage_error(e: impl std::fmt::Debug) -> EnigmatickError {
+    EnigmatickError::Internal(format!("storage error: {e:?}"))
+}
+
+#[cfg(target_arch = "wasm32")]
+mod backend {
+    use super::storage_error;
+    use crate::EnigmatickResult;
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{
+        IdbDatabase, IdbFactory, IdbObjectStore, IdbOpenDbRequest, IdbRequest, IdbTransactionMode,
+    };
+
+    const DB_NAME: &str = "enigmatick";
+    const DB_VERSION: u32 = 1;
+    const STORE: &str = "kv";
+
+    // Wraps the success/error callbacks of an IdbRequest in a Promise
+    fn complete(request: &IdbRequest) -> JsFuture {
+        let promise = js_sys::Promise::new(&mut |resolve, reject| {
+            let success = {
+                let request = request.clone();
+                Closure::once_into_js(move |_: web_sys::Event| {
+                    let result = request.result().unwrap_or(JsValue::UNDEFINED);
+                    resolve.call1(&JsValue::NULL, &result).ok();
+                })
+            };
+
+            let failure = {
+                let request = request.clone();
+                Closure::once_into_js(move |_: web_sys::Event| {
+                    let error = request
+                        .error()
+                        .ok()
+                        .flatten()
+                        .map(JsValue::from)
+                        .unwrap_or(JsValue::UNDEFINED);
+                    reject.call1(&JsValue::NULL, &error).ok();
+                })
+            };
+
+            request.set_onsuccess(Some(success.unchecked_ref()));
+            request.set_onerror(Some(failure.unchecked_ref()));
+        });
+
+        JsFuture::from(promise)
+    }
+
+    async fn open() -> EnigmatickResult<IdbDatabase> {
+        // indexedDB is read from the global object so that this works in
+        // both a Window and a Worker
+        let factory: IdbFactory = js_sys::Reflect::get(&js_sys::global(), &"indexedDB".into())
+            .map_err(storage_error)?
+            .dyn_into()
+            .map_err(storage_error)?;
+
+        let request: IdbOpenDbRequest = factory
+            .open_with_u32(DB_NAME, DB_VERSION)
+            .map_err(storage_error)?;
+
+        let upgrade = {
+            let request = request.clone();
+            Closure::once_into_js(move |_: web_sys::Event| {
+                if let Some(db) = request
+                    .result()
+                    .ok()
+                    .and_then(|x| x.dyn_into::<IdbDatabase>().ok())
+                {
+                    if !db.object_store_names().contains(STORE) {
+                        db.create_object_store(STORE).ok();
+                    }
+                }
+            })
+        };
+        request.set_onupgradeneeded(Some(upgrade.unchecked_ref()));
+
+        complete(&request)
+            .await
+            .map_err(storage_error)?
+            .dyn_into()
+            .map_err(storage_error)
+    }
+
+    async fn store(mode: IdbTransactionMode) -> EnigmatickResult<IdbObjectStore> {
+        open()
+            .await?
+            .transaction_with_str_and_mode(STORE, mode)
+            .map_err(storage_error)?
+            .object_store(STORE)
+            .map_err(storage_error)
+    }
+
+    pub async fn load(key: &str) -> EnigmatickResult<Option<String>> {
+        let request = store(IdbTransactionMode::Readonly)
+            .await?
+            .get(&key.into())
+            .map_err(storage_error)?;
+
+        Ok(complete(&request).await.map_err(storage_error)?.as_string())
+    }
+
+    pub async fn save(key: &str, value: &str) -> EnigmatickResult<()> {
+        let request = store(IdbTransactionMode::Readwrite)
+            .await?
+            .put_with_key(&value.into(), &key.into())
+            .map_err(storage_error)?;
+
+        complete(&request).await.map_err(storage_error)?;
+        Ok(())
+    }
+
+    pub async fn remove(key: &str) -> EnigmatickResult<()> {
+        let request = store(IdbTransactionMode::Readwrite)
+            .await?
+            .delete(&key.into())
+            .map_err(storage_error)?;
+
+        complete(&request).await.map_err(storage_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod backend {
+    use super::storage_error;
+    use crate::EnigmatickResult;
+    use std::path::{Path, PathBuf};
+
+    pub fn home() -> PathBuf {
+        std::env::var_os("ENIGMATICK_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|x| PathBuf::from(x).join(".enigmatick")))
+            .unwrap_or_else(|| PathBuf::from(".enigmatick"))
+    }
+
+    fn path(dir: &Path, key: &str) -> PathBuf {
+        // keys are internal identifiers, but keep them from escaping the directory
+        let name: String = key
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        dir.join(format!("{name}.json"))
+    }
+
+    pub fn load_in(dir: &Path, key: &str) -> EnigmatickResult<Option<String>> {
+        match std::fs::read_to_string(path(dir, key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    pub fn save_in(dir: &Path, key: &str, value: &str) -> EnigmatickResult<()> {
+        std::fs::create_dir_all(dir).map_err(storage_error)?;
+
+        // write to a temporary file first so that a crash cannot leave a
+        // truncated value behind
+        let path = path(dir, key);
+        let temporary = path.with_extension("json.tmp");
+        std::fs::write(&temporary, value).map_err(storage_error)?;
+        std::fs::rename(&temporary, &path).map_err(storage_error)
+    }
+
+    pub fn remove_in(dir: &Path, key: &str) -> EnigmatickResult<()> {
+        match std::fs::remove_file(path(dir, key)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    // the active account's directory, if it was given one
+    fn dir() -> PathBuf {
+        crate::current_account()
+            .storage_dir
+            .lock()
+            .ok()
+            .and_then(|x| x.clone())
+            .unwrap_or_else(home)
+    }
+
+    pub async fn load(key: &str) -> EnigmatickResult<Option<String>> {
+        load_in(&dir(), key)
+    }
+
+    pub async fn save(key: &str, value: &str) -> EnigmatickResult<()> {
+        save_in(&dir(), key, value)
+    }
+
+    pub async fn remove(key: &str) -> EnigmatickResult<()> {
+        remove_in(&dir(), key)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use backend::home as storage_home;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use backend::{load_in, remove_in, save_in};
+
+pub async fn storage_load(key: &str) -> EnigmatickResult<Option<String>> {
+    backend::load(key).await
+}
+
+pub async fn storage_save(key: &str, value: &str) -> EnigmatickResult<()> {
+    backend::save(key, value).await
+}
+
+pub async fn storage_remove(key: &str) -> EnigmatickResult<()> {
+    backend::remove(key).await
+}
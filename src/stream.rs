@@ -1,15 +1,13 @@
 use serde::{Deserialize, Serialize};
-use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::{authenticated, send_post, EnigmatickState, Profile};
+use crate::{authenticated, send_post, EnigmatickError, EnigmatickState, Profile};
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct StreamAuthorization {
     uuid: String,
 }
 
-#[wasm_bindgen]
-pub async fn send_authorization(uuid: String) -> bool {
+pub async fn send_authorization(uuid: String) -> Result<bool, EnigmatickError> {
     authenticated(move |_: EnigmatickState, profile: Profile| async move {
         let endpoint = format!("/api/user/{}/events/authorize", profile.username.clone());
 
@@ -17,11 +15,12 @@ pub async fn send_authorization(uuid: String) -> bool {
 
         send_post(
             endpoint,
-            serde_json::to_string(&authorization).unwrap(),
+            serde_json::to_string(&authorization)?,
             "application/activity+json".to_string(),
         )
-        .await
+        .await?;
+
+        Ok(true)
     })
     .await
-    .is_some()
 }
@@ -1,15 +1,16 @@
 use std::collections::HashMap;
 
 use crate::{
-    authenticated, decrypt, get_object, get_state, log, retrieve_credentials, send_get, send_post,
-    EnigmatickState, Profile, ENCRYPT_FN,
+    authenticated, current_account, decrypt, get_object, get_state, log, retrieve_credentials,
+    send_get, send_post, unless_cancelled, with_current_client, EncryptedMessage, EnigmatickError,
+    EnigmatickResult, EnigmatickState, Profile, ENCRYPT_FN,
 };
 use anyhow::{anyhow, Result};
 use base64::engine::{general_purpose, Engine as _};
+use jdt_activity_pub::MaybeReference;
 use jdt_activity_pub::{
     ActivityPub, ApActivity, ApCollection, ApCreate, ApInstrument, ApNote, ApObject, Collectible,
 };
-use jdt_activity_pub::MaybeReference;
 use openmls::{
     group::{GroupId, MlsGroup, MlsGroupJoinConfig, StagedWelcome},
     prelude::{
@@ -18,10 +19,8 @@ use openmls::{
     },
 };
 use openmls_rust_crypto::OpenMlsRustCrypto;
-use serde_json::json;
-use serde_wasm_bindgen;
+use serde_json::{json, Value};
 use urlencoding::encode;
-use wasm_bindgen::prelude::{wasm_bindgen, JsValue};
 use wasm_bindgen_futures::spawn_local;
 
 pub fn convert_hashtags_to_query_string(hashtags: &[String]) -> String {
@@ -32,18 +31,16 @@ pub fn convert_hashtags_to_query_string(hashtags: &[String]) -> String {
         .join("")
 }
 
-#[wasm_bindgen]
-pub async fn get_timeline(
+pub async fn get_timeline_with_hashtags(
     max: Option<String>,
     min: Option<String>,
     limit: i32,
     view: String,
-    hashtags: JsValue,
-) -> Option<String> {
+    hashtags: Vec<String>,
+) -> Result<String, EnigmatickError> {
     //log("IN get_timeline");
     let state = get_state();
 
-    let hashtags: Vec<String> = serde_wasm_bindgen::from_value(hashtags).unwrap_or_default();
     let hashtags = convert_hashtags_to_query_string(&hashtags);
 
     //log(&hashtags);
@@ -142,7 +139,9 @@ pub async fn get_timeline(
         group: MlsGroup,
     ) -> Result<Vec<ApInstrument>> {
         let mut instruments = vec![];
-        let encrypted_decoded = general_purpose::STANDARD.decode(note.content.ok_or(anyhow!("content must be Some"))?).unwrap();
+        let encrypted_decoded = general_purpose::STANDARD
+            .decode(note.content.ok_or(anyhow!("content must be Some"))?)
+            .unwrap();
         let encrypted_deserialized =
             MlsMessageIn::tls_deserialize(&mut encrypted_decoded.as_slice()).unwrap();
 
@@ -207,7 +206,8 @@ pub async fn get_timeline(
                     .await
                 },
             )
-            .await;
+            .await
+            .ok();
         }
     }
 
@@ -247,16 +247,30 @@ pub async fn get_timeline(
             })
     }
 
-    fn transform_encrypted_activity(create: ApCreate, mut note: ApNote) -> Option<ActivityPub> {
+    // The note's attachments are added as encryptedAttachments, each of which
+    // can be passed to decrypt_attachment
+    fn transform_encrypted_activity(create: ApCreate, mut note: ApNote) -> Option<Value> {
         find_vault_instrument(&create).and_then(|instrument| {
-            decrypt_instrument_content(&instrument).map(|decrypted| {
-                note.content = Some(decrypted);
-                build_activity(create.clone(), note.clone())
+            decrypt_instrument_content(&instrument).and_then(|decrypted| {
+                let message = EncryptedMessage::parse(&decrypted);
+                note.content = Some(message.content);
+
+                let mut activity =
+                    serde_json::to_value(build_activity(create.clone(), note.clone())).ok()?;
+
+                if !message.attachments.is_empty() {
+                    activity.get_mut("object")?.as_object_mut()?.insert(
+                        "encryptedAttachments".to_string(),
+                        serde_json::to_value(&message.attachments).ok()?,
+                    );
+                }
+
+                Some(activity)
             })
         })
     }
 
-    async fn retrieve_encrypted_notes() -> Option<String> {
+    async fn retrieve_encrypted_notes() -> EnigmatickResult<String> {
         authenticated(
             move |_state: EnigmatickState, _profile: Profile| async move {
                 let url = format!("/api/encrypted");
@@ -270,7 +284,7 @@ pub async fn get_timeline(
     async fn process_encrypted_notes(
         provider: &mut OpenMlsRustCrypto,
     ) -> Option<Vec<ApInstrument>> {
-        if let Some(text) = retrieve_encrypted_notes().await {
+        if let Ok(text) = retrieve_encrypted_notes().await {
             let mut instruments: Vec<ApInstrument> = vec![];
             let mut groups = HashMap::<String, GroupId>::new();
 
@@ -330,7 +344,11 @@ pub async fn get_timeline(
                 if view.to_lowercase().as_str() == "direct" {
                     decrypt_task().await;
                 } else {
-                    spawn_local(decrypt_task());
+                    // stopped if the account logs out before it finishes
+                    let session = current_account().session();
+                    spawn_local(with_current_client(async move {
+                        unless_cancelled(&session, decrypt_task()).await;
+                    }));
                 }
 
                 let url =
@@ -338,23 +356,32 @@ pub async fn get_timeline(
 
                 let text = send_get(None, url, "application/activity+json".to_string()).await?;
 
-                if let ApObject::Collection(object) = serde_json::from_str(&text).ok()? {
-                    let items = object.clone().items()?;
+                if let ApObject::Collection(object) = serde_json::from_str(&text)? {
+                    let items = object.clone().items().unwrap_or_default();
 
-                    let _decrypted_items: Vec<ActivityPub> = items
+                    let decrypted_items = items
                         .iter()
-                        .filter_map(|item| {
+                        .map(|item| {
                             is_encrypted_note(item)
                                 .and_then(|(create, note)| {
                                     transform_encrypted_activity(create, note)
                                 })
-                                .or_else(|| Some(item.clone()))
+                                .map_or_else(|| serde_json::to_value(item), Ok)
                         })
-                        .collect();
+                        .collect::<serde_json::Result<Vec<Value>>>()?;
 
-                    serde_json::to_string(&object).ok()
+                    let field = if object.items.is_some() {
+                        "items"
+                    } else {
+                        "orderedItems"
+                    };
+
+                    let mut collection = serde_json::to_value(&object)?;
+                    collection[field] = Value::Array(decrypted_items);
+
+                    Ok(serde_json::to_string(&collection)?)
                 } else {
-                    None
+                    Err(EnigmatickError::parse("inbox response is not a Collection"))
                 }
             },
         )
@@ -366,15 +393,13 @@ pub async fn get_timeline(
             None,
             "application/activity+json",
         )
-        .await
-        .ok()?;
+        .await?;
 
-        serde_json::to_string(&object).ok()
+        Ok(serde_json::to_string(&object)?)
     }
 }
 
-#[wasm_bindgen]
-pub async fn get_conversation(conversation: String, limit: i32) -> Option<String> {
+pub async fn get_conversation(conversation: String, limit: i32) -> Result<String, EnigmatickError> {
     authenticated(
         move |_state: EnigmatickState, _profile: Profile| async move {
             let conversation = urlencoding::encode(&conversation).to_string();
@@ -0,0 +1,413 @@
+use futures::future::LocalBoxFuture;
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use crate::{EnigmatickError, EnigmatickResult, Method, RequestOptions, SignResponse};
+
+lazy_static! {
+    static ref TRANSPORT: Mutex<Arc<dyn Transport>> = Mutex::new(default_transport());
+}
+
+thread_local! {
+    static SCOPED_TRANSPORT: RefCell<Option<Arc<dyn Transport>>> = const { RefCell::new(None) };
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+lazy_static! {
+    // reqwest::Client pools connections internally and is cheap to clone, so every
+    // ReqwestTransport shares this one instead of building a client per request
+    static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::new();
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    // timeout and cancellation; defaults to the options in effect where the
+    // request is built (see with_request_options)
+    pub options: RequestOptions,
+}
+
+impl HttpRequest {
+    pub fn new(method: Method, url: String) -> Self {
+        HttpRequest {
+            method,
+            url,
+            headers: vec![],
+            body: None,
+            options: RequestOptions::current(),
+        }
+    }
+
+    pub fn options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    // Applies the headers produced by crypto::sign; the digest headers are only
+    // present when the signature covered a body, and Signature-Input only for
+    // RFC 9421 signatures
+    pub fn signature(mut self, signature: Option<SignResponse>) -> Self {
+        if let Some(signature) = signature {
+            self = self.header("Enigmatick-Date", &signature.date);
+
+            if let Some(digest) = &signature.digest {
+                self = self.header("Digest", digest);
+            }
+
+            if let Some(content_digest) = &signature.content_digest {
+                self = self.header("Content-Digest", content_digest);
+            }
+
+            if let Some(signature_input) = &signature.signature_input {
+                self = self.header("Signature-Input", signature_input);
+            }
+
+            self = self.header("Signature", &signature.signature);
+        }
+
+        self
+    }
+
+    pub fn host(&self) -> Option<String> {
+        url::Url::parse(&self.url)
+            .ok()
+            .and_then(|x| x.host_str().map(str::to_string))
+    }
+
+    // The path and query of the URL, used to match requests against recorded
+    // responses regardless of the origin they were sent to
+    pub fn path(&self) -> &str {
+        match self.url.find("://") {
+            Some(scheme) => {
+                let rest = &self.url[scheme + 3..];
+                rest.find('/').map(|x| &rest[x..]).unwrap_or("/")
+            }
+            None => &self.url,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn new(status: u16, body: &str) -> Self {
+        HttpResponse {
+            status,
+            headers: vec![],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn ok(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn text(&self) -> EnigmatickResult<String> {
+        Ok(String::from_utf8(self.body.clone())?)
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> EnigmatickResult<T> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+
+    // Converts a non-2xx response into EnigmatickError::Http carrying the status
+    // and whatever text the server sent back
+    pub fn error_for_status(self) -> EnigmatickResult<Self> {
+        if self.ok() {
+            Ok(self)
+        } else {
+            Err(EnigmatickError::Http {
+                status: self.status,
+                message: String::from_utf8_lossy(&self.body).to_string(),
+            })
+        }
+    }
+}
+
+// Everything that talks to the network goes through a Transport. The default
+// is gloo-net in the browser and reqwest natively; tests and tools can swap in
+// a MockTransport via set_transport. Implementations are expected to honor
+// request.options, failing with Timeout or Cancelled when interrupted.
+pub trait Transport: Send + Sync {
+    fn send(&self, request: HttpRequest) -> LocalBoxFuture<'_, EnigmatickResult<HttpResponse>>;
+}
+
+pub fn set_transport(transport: Arc<dyn Transport>) {
+    if let Ok(mut x) = TRANSPORT.lock() {
+        *x = transport;
+    }
+}
+
+// The transport of the enclosing with_transport scope, if any, or the one set
+// with set_transport
+pub fn get_transport() -> Arc<dyn Transport> {
+    if let Some(transport) = SCOPED_TRANSPORT.with(|x| x.borrow().clone()) {
+        return transport;
+    }
+
+    TRANSPORT
+        .lock()
+        .map(|x| x.clone())
+        .unwrap_or_else(|_| default_transport())
+}
+
+// Sends every request started while f is being polled through transport
+pub fn with_transport<F: Future>(
+    transport: Arc<dyn Transport>,
+    f: F,
+) -> impl Future<Output = F::Output> {
+    WithTransport {
+        transport,
+        inner: Box::pin(f),
+    }
+}
+
+struct WithTransport<F: Future> {
+    transport: Arc<dyn Transport>,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for WithTransport<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let previous = SCOPED_TRANSPORT.with(|x| x.replace(Some(self.transport.clone())));
+        let result = self.inner.as_mut().poll(cx);
+        SCOPED_TRANSPORT.with(|x| *x.borrow_mut() = previous);
+
+        result
+    }
+}
+
+fn network_error(e: impl std::fmt::Display) -> EnigmatickError {
+    EnigmatickError::Network(e.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn default_transport() -> Arc<dyn Transport> {
+    Arc::new(GlooTransport)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_transport() -> Arc<dyn Transport> {
+    Arc::new(ReqwestTransport::default())
+}
+
+#[cfg(target_arch = "wasm32")]
+#[derive(Clone, Debug, Default)]
+pub struct GlooTransport;
+
+#[cfg(target_arch = "wasm32")]
+impl Transport for GlooTransport {
+    fn send(&self, request: HttpRequest) -> LocalBoxFuture<'_, EnigmatickResult<HttpResponse>> {
+        use gloo_net::http::{Method as GlooMethod, Request};
+
+        Box::pin(async move {
+            let method = match request.method {
+                Method::Get => GlooMethod::GET,
+                Method::Post => GlooMethod::POST,
+            };
+
+            let controller =
+                web_sys::AbortController::new().map_err(|e| network_error(format!("{e:?}")))?;
+            let mut client = Request::new(&request.url)
+                .method(method)
+                .abort_signal(Some(&controller.signal()));
+
+            for (name, value) in &request.headers {
+                client = client.header(name, value);
+            }
+
+            if let Some(body) = &request.body {
+                client = client.body(js_sys::Uint8Array::from(body.as_slice()));
+            }
+
+            let result = request
+                .options
+                .guard(async {
+                    let response = client.send().await.map_err(network_error)?;
+
+                    Ok(HttpResponse {
+                        status: response.status(),
+                        headers: response.headers().entries().collect(),
+                        body: response.binary().await.map_err(network_error)?,
+                    })
+                })
+                .await;
+
+            // dropping the future leaves the fetch running in the browser
+            if matches!(
+                result,
+                Err(EnigmatickError::Timeout(_)) | Err(EnigmatickError::Cancelled)
+            ) {
+                controller.abort();
+            }
+
+            result
+        })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for ReqwestTransport {
+    fn default() -> Self {
+        ReqwestTransport {
+            client: HTTP_CLIENT.clone(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ReqwestTransport {
+    pub fn new(client: reqwest::Client) -> Self {
+        ReqwestTransport { client }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Transport for ReqwestTransport {
+    fn send(&self, request: HttpRequest) -> LocalBoxFuture<'_, EnigmatickResult<HttpResponse>> {
+        Box::pin(async move {
+            let method = match request.method {
+                Method::Get => reqwest::Method::GET,
+                Method::Post => reqwest::Method::POST,
+            };
+
+            let mut client = self.client.request(method, &request.url);
+
+            for (name, value) in &request.headers {
+                client = client.header(name, value);
+            }
+
+            if let Some(body) = request.body {
+                client = client.body(body);
+            }
+
+            // dropping the reqwest future cancels the request
+            request
+                .options
+                .guard(async {
+                    let response = client.send().await.map_err(network_error)?;
+
+                    let status = response.status().as_u16();
+                    let headers = response
+                        .headers()
+                        .iter()
+                        .filter_map(|(name, value)| {
+                            Some((name.to_string(), value.to_str().ok()?.to_string()))
+                        })
+                        .collect();
+
+                    Ok(HttpResponse {
+                        status,
+                        headers,
+                        body: response.bytes().await.map_err(network_error)?.to_vec(),
+                    })
+                })
+                .await
+        })
+    }
+}
+
+// An in-memory Transport that answers from queued responses keyed by method
+// and path (including the query string). The last response queued for a key
+// is replayed for any further requests. Every request is recorded so that
+// callers can inspect what would have been sent.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    responses: Mutex<HashMap<String, VecDeque<HttpResponse>>>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        MockTransport::default()
+    }
+
+    fn key(method: &Method, path: &str) -> String {
+        format!("{method} {path}")
+    }
+
+    pub fn respond(&self, method: Method, path: &str, response: HttpResponse) {
+        if let Ok(mut responses) = self.responses.lock() {
+            responses
+                .entry(MockTransport::key(&method, path))
+                .or_default()
+                .push_back(response);
+        }
+    }
+
+    pub fn respond_json<T: serde::Serialize>(&self, method: Method, path: &str, object: &T) {
+        let body = serde_json::to_string(object).unwrap_or_default();
+        self.respond(method, path, HttpResponse::new(200, &body));
+    }
+
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().map(|x| x.clone()).unwrap_or_default()
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(&self, request: HttpRequest) -> LocalBoxFuture<'_, EnigmatickResult<HttpResponse>> {
+        Box::pin(async move {
+            let key = MockTransport::key(&request.method, request.path());
+            let options = request.options.clone();
+
+            if let Ok(mut requests) = self.requests.lock() {
+                requests.push(request);
+            }
+
+            options
+                .guard(async {
+                    let missing = || network_error(format!("no recorded response for {key}"));
+
+                    let mut responses = self.responses.lock().map_err(network_error)?;
+                    let queue = responses.get_mut(&key).ok_or_else(missing)?;
+
+                    if queue.len() > 1 {
+                        queue.pop_front().ok_or_else(missing)
+                    } else {
+                        queue.front().cloned().ok_or_else(missing)
+                    }
+                })
+                .await
+        })
+    }
+}
@@ -1,23 +1,21 @@
 use jdt_activity_pub::{ApAddress, ApObject, ApUpdate, MaybeReference};
-use wasm_bindgen::prelude::wasm_bindgen;
 
 use crate::{
-    authenticated, log, send_post, ArticleParams, EnigmatickState, NoteParams, Profile, QuestionParams,
+    authenticated, log, send_activity, ArticleParams, EnigmatickError, EnigmatickState, NoteParams,
+    Profile, QuestionParams,
 };
 
-#[wasm_bindgen]
-pub async fn send_update(_object_id: String, updated_object_json: String) -> Option<String> {
-    authenticated(move |state: EnigmatickState, profile: Profile| async move {
+pub async fn send_update(_object_id: String, updated_object_json: String) -> Result<String, EnigmatickError> {
+    authenticated(move |_state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
-        let actor_id = state.profile?.id.clone();
+        let actor_id = profile.id.clone();
 
         // Parse the updated object JSON into an ApObject
         let updated_object: ApObject = serde_json::from_str(&updated_object_json)
             .map_err(|e| {
                 log(&format!("Failed to parse updated object JSON: {}", e));
                 e
-            })
-            .ok()?;
+            })?;
 
         // Verify the object's ID matches the object_id parameter
         // The object should have its ID set by the frontend, but we verify it matches
@@ -34,21 +32,15 @@ pub async fn send_update(_object_id: String, updated_object_json: String) -> Opt
 
         log(&format!("UPDATE ACTIVITY\n{}", serde_json::to_string_pretty(&update_activity).unwrap()));
 
-        send_post(
-            outbox,
-            serde_json::to_string(&update_activity).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &update_activity).await
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn send_update_note(params: &mut NoteParams) -> Option<String> {
+pub async fn send_update_note(params: &mut NoteParams) -> Result<String, EnigmatickError> {
     authenticated(move |state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
-        let mut note = params.clone().to_note().await;
+        let mut note = params.clone().to_note().await?;
         
         // Ensure attributed_to is set correctly
         let actor_id = state.profile.as_ref().map(|p| p.id.clone());
@@ -75,18 +67,12 @@ pub async fn send_update_note(params: &mut NoteParams) -> Option<String> {
 
         log(&format!("UPDATE ACTIVITY\n{}", serde_json::to_string_pretty(&update_activity).unwrap()));
 
-        send_post(
-            outbox,
-            serde_json::to_string(&update_activity).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &update_activity).await
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn send_update_article(params: &mut ArticleParams) -> Option<String> {
+pub async fn send_update_article(params: &mut ArticleParams) -> Result<String, EnigmatickError> {
     authenticated(move |state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
         let mut article = params.to_article();
@@ -110,18 +96,12 @@ pub async fn send_update_article(params: &mut ArticleParams) -> Option<String> {
 
         log(&format!("UPDATE ACTIVITY\n{}", serde_json::to_string_pretty(&update_activity).unwrap()));
 
-        send_post(
-            outbox,
-            serde_json::to_string(&update_activity).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &update_activity).await
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn send_update_question(params: &mut QuestionParams) -> Option<String> {
+pub async fn send_update_question(params: &mut QuestionParams) -> Result<String, EnigmatickError> {
     authenticated(move |state: EnigmatickState, profile: Profile| async move {
         let outbox = format!("/user/{}/outbox", profile.username.clone());
         let mut question = params.to_question();
@@ -145,12 +125,7 @@ pub async fn send_update_question(params: &mut QuestionParams) -> Option<String>
 
         log(&format!("UPDATE ACTIVITY\n{}", serde_json::to_string_pretty(&update_activity).unwrap()));
 
-        send_post(
-            outbox,
-            serde_json::to_string(&update_activity).unwrap(),
-            "application/activity+json".to_string(),
-        )
-        .await
+        send_activity(outbox, &update_activity).await
     })
     .await
 }
@@ -1,20 +1,27 @@
 use base64::{engine::general_purpose, engine::Engine as _};
-use jdt_activity_pub::{ApActor, ApAddress, ApCollection, ApInstrument};
+use jdt_activity_pub::{ApActor, ApAddress, ApAttachment, ApCollection, ApInstrument};
+use openmls::prelude::OpenMlsProvider;
 use orion::{aead, kdf};
-use rsa::pkcs8::{EncodePrivateKey, EncodePublicKey, LineEnding};
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::wasm_bindgen;
 
 use crate::{
-    authenticated, decrypt, derive_key, encode_derived_key, encrypt, get_hash, get_key_pair,
-    get_object, log, post_object, send_get, send_post, update_state, update_state_password,
-    upload_file, EnigmatickState,
+    authenticated, begin_account, clear_persisted_state, client_signing_key, current_account,
+    decrypt, derive_key, derive_previous_keys, emit, encode_derived_key, encrypt_bytes,
+    generate_client_key, get_hash, get_object, get_state, log, persisted_username, post_object,
+    publish_client_key, recovery_setup, retrieve_credentials, rewrap_recovery,
+    schedule_outbox_drain, send_get, send_post, srp_prove, srp_prove_or_legacy, srp_verifier,
+    storage_load, storage_save, unregister_account, update_secrets, update_state,
+    update_state_password, upload_file, with_secrets, EnigmatickError, EnigmatickEvent,
+    EnigmatickResult, EnigmatickState, KdfParams, KeyAlgorithm, RecoverySetup, RecoveryWrap,
+    SecretString, SrpVerifier, DEFAULT_KEY_GRACE_PERIOD, ENCRYPT_FN, HASH_FN,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NewUser {
     pub username: String,
-    pub password: String,
+    #[serde(flatten)]
+    pub srp: SrpVerifier,
     pub display_name: String,
     pub client_public_key: Option<String>,
     pub client_private_key: Option<String>,
@@ -22,6 +29,9 @@ pub struct NewUser {
     pub olm_pickled_account_hash: Option<String>,
     pub olm_identity_key: Option<String>,
     pub salt: Option<String>,
+    pub kdf: Option<KdfParams>,
+    #[serde(flatten)]
+    pub recovery: Option<RecoverySetup>,
 }
 
 #[wasm_bindgen(getter_with_clone)]
@@ -37,71 +47,144 @@ pub struct Profile {
     pub summary: Option<String>,
     pub public_key: String,
     pub client_public_key: Option<String>,
+    // the keyId for client_public_key; None until the key is first rotated
+    #[serde(default)]
+    pub client_key_id: Option<String>,
     pub avatar_filename: Option<String>,
     pub banner_filename: Option<String>,
     pub salt: Option<String>,
+    // None for accounts created before KDF parameters were stored
+    #[wasm_bindgen(skip)]
+    #[serde(default)]
+    pub kdf: Option<KdfParams>,
     pub client_private_key: Option<String>,
     pub olm_pickled_account: Option<String>,
     pub olm_identity_key: Option<String>,
+    // the derived key sealed under the recovery phrase, and the reverse (see
+    // recovery.rs); None when no phrase has been set up
+    #[serde(default)]
+    pub recovery_key: Option<String>,
+    #[serde(default)]
+    pub recovery_escrow: Option<String>,
     pub followers: Option<String>,
     pub following: Option<String>,
 }
 
-#[wasm_bindgen]
-pub async fn authenticate(username: String, password_str: String) -> Option<Profile> {
+pub async fn authenticate(
+    username: String,
+    password_str: String,
+) -> Result<Profile, EnigmatickError> {
+    sign_in(username, password_str, false).await
+}
+
+// As authenticate, but an account created before SRP is logged in with the
+// password hash and given a verifier. Only for callers that know the account
+// still needs that migration: the hash is never sent for an account that has
+// logged in with SRP on this device.
+pub async fn authenticate_legacy(
+    username: String,
+    password_str: String,
+) -> Result<Profile, EnigmatickError> {
+    sign_in(username, password_str, true).await
+}
+
+async fn sign_in(
+    username: String,
+    password_str: String,
+    allow_legacy: bool,
+) -> EnigmatickResult<Profile> {
     #[derive(Serialize, Debug, Clone)]
     struct AuthenticationData {
         username: String,
-        password: String,
+        session: String,
+        m1: String,
     }
 
-    let password_hash = get_hash(password_str.clone().into_bytes())?;
-    let req = AuthenticationData {
-        username,
-        password: general_purpose::STANDARD.encode(password_hash),
-    };
+    #[derive(Deserialize)]
+    struct Authenticated {
+        profile: Profile,
+        m2: String,
+    }
 
-    let response = post_object(
-        "/api/user/authenticate".to_string(),
-        req,
-        "application/json",
-        None,
+    let proof = srp_prove_or_legacy(
+        "/api/user/authenticate/start".to_string(),
+        &username,
+        &password_str,
     )
     .await?;
 
-    let user: Profile = serde_json::from_str(&response).ok()?;
+    // the server alone deciding that an account is legacy would be enough
+    // to get the hash out of any client
+    let legacy = proof.is_none();
+    if legacy && !allow_legacy {
+        return Err(EnigmatickError::Verification(
+            "the account has no SRP verifier yet; authenticate_legacy migrates it".to_string(),
+        ));
+    }
+    if legacy && srp_completed(&username).await? {
+        return Err(EnigmatickError::Verification(
+            "the server asked for a password hash for an account that uses SRP".to_string(),
+        ));
+    }
+
+    let user = match proof {
+        Some(proof) => {
+            let req = AuthenticationData {
+                username,
+                session: proof.session.clone(),
+                m1: proof.m1.clone(),
+            };
+
+            let response = post_object(
+                "/api/user/authenticate".to_string(),
+                req,
+                "application/json",
+                None,
+            )
+            .await?;
+
+            let authenticated: Authenticated = serde_json::from_str(&response)?;
+            proof.verify_server(&authenticated.m2)?;
+            remember_srp(&authenticated.profile.username).await;
+
+            authenticated.profile
+        }
+        None => legacy_authenticate(username, &password_str).await?,
+    };
     //log(&format!("PROFILE\n{user:#?}"));
 
+    // another account that is signed in stays signed in
+    begin_account();
+
     update_state(|state| {
         state.authenticated = true;
         state.set_profile(user.clone());
         Ok(())
-    })
-    .ok()?;
+    })?;
 
     if let (Some(salt), Some(client_private_key), Some(pickled_account)) = (
         user.salt.clone(),
         user.client_private_key.clone(),
         user.olm_pickled_account.clone(),
     ) {
-        let derived_key = derive_key(password_str, salt).ok()?;
+        let params = KdfParams::of(&user);
+        let derived_key = derive_key(password_str.clone(), salt.clone(), &params)?;
         let encoded_derived_key = encode_derived_key(&derived_key);
+        let previous_keys = derive_previous_keys(password_str.clone(), salt, &params)?;
 
         update_state(|state| {
             state.set_derived_key(encoded_derived_key.clone());
+            state.set_previous_derived_keys(previous_keys);
             Ok(())
-        })
-        .ok()?;
+        })?;
 
-        let client_private_key =
-            decrypt(Some(encoded_derived_key.clone()), client_private_key).ok()?;
+        let client_private_key = decrypt(Some(encoded_derived_key.clone()), client_private_key)?;
         update_state(|state| {
             state.set_client_private_key_pem(client_private_key);
             Ok(())
-        })
-        .ok()?;
+        })?;
 
-        let pickled_account = decrypt(Some(encoded_derived_key), pickled_account).ok()?;
+        let pickled_account = decrypt(Some(encoded_derived_key), pickled_account)?;
         log(&format!(
             "Olm Pickled Account Hash: {}",
             get_hash(pickled_account.clone().into_bytes()).unwrap_or_default()
@@ -109,41 +192,284 @@ pub async fn authenticate(username: String, password_str: String) -> Option<Prof
         update_state(|state| {
             state.set_olm_pickled_account(pickled_account);
             Ok(())
-        })
-        .ok()?;
+        })?;
+
+        // as with the KDF migration below (which needs the verifier), a
+        // failure is retried at the next login
+        if legacy {
+            match register_srp_verifier(&user, &password_str).await {
+                Ok(()) => remember_srp(&user.username).await,
+                Err(e) => log(&format!("SRP verifier registration failed: {e}")),
+            }
+        }
+
+        // a failed migration leaves the account usable on its old parameters;
+        // it will be attempted again at the next login
+        if params.is_outdated() {
+            if let Err(e) = migrate_kdf(password_str).await {
+                log(&format!("KDF migration failed: {e}"));
+            }
+        }
+
+        // activities queued while offline in an earlier session can be
+        // signed again now that the client key is available
+        schedule_outbox_drain().await;
     }
 
-    Some(user)
+    Ok(get_state().profile.unwrap_or(user))
+}
+
+// Logs in an account created before SRP, which the server only has a password
+// hash for; the hash is sent as it was before SRP, this one last time
+async fn legacy_authenticate(username: String, password_str: &str) -> EnigmatickResult<Profile> {
+    #[derive(Serialize)]
+    struct LegacyAuthenticationData {
+        username: String,
+        password: String,
+    }
+
+    let password_hash = get_hash(password_str.as_bytes().to_vec())?;
+    let req = LegacyAuthenticationData {
+        username,
+        password: general_purpose::STANDARD.encode(password_hash),
+    };
+
+    let response = post_object(
+        "/api/user/authenticate".to_string(),
+        req,
+        "application/json",
+        None,
+    )
+    .await?;
+
+    Ok(serde_json::from_str(&response)?)
+}
+
+// Where a username's first SRP login on the active server is remembered
+fn srp_key(username: &str) -> String {
+    format!(
+        "srp-{}-{username}",
+        get_state().server_name.unwrap_or_default()
+    )
+}
+
+async fn srp_completed(username: &str) -> EnigmatickResult<bool> {
+    Ok(storage_load(&srp_key(username)).await?.is_some())
+}
+
+// A failure here only means that authenticate_legacy stays possible
+async fn remember_srp(username: &str) {
+    if let Err(e) = storage_save(&srp_key(username), "true").await {
+        log(&format!("unable to remember the SRP login: {e}"));
+    }
+}
+
+// Gives a legacy account an SRP verifier, after which the server drops its
+// password hash and later logins use SRP
+async fn register_srp_verifier(profile: &Profile, password_str: &str) -> EnigmatickResult<()> {
+    let url = format!("/api/user/{}/srp", profile.username);
+    let data = serde_json::to_string(&srp_verifier(&profile.username, password_str))?;
+
+    let resp = send_post(url, data, "application/json".to_string()).await?;
+
+    if resp != "200" {
+        return Err(EnigmatickError::parse(format!(
+            "unexpected response to SRP verifier registration: {resp}"
+        )));
+    }
+
+    Ok(())
+}
+
+// Ends the session of the active account. Whatever is in flight for it is
+// abandoned, the server is asked to invalidate the session, and the profile,
+// secrets, saved session and cached responses are dropped. The local teardown
+// happens even if the server cannot be reached; that error is returned after.
+pub async fn logout() -> Result<(), EnigmatickError> {
+    let account = current_account();
+    let profile = get_state()
+        .profile
+        .ok_or(EnigmatickError::NotAuthenticated)?;
+
+    account.end_session();
+
+    let url = format!("/api/user/{}/logout", profile.username);
+    let invalidated = send_post(url, String::new(), "application/json".to_string()).await;
+
+    if persisted_username().await.ok().flatten().as_ref() == Some(&profile.username) {
+        clear_persisted_state().await.ok();
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    crate::clear_cached_for(&profile.id.to_string());
+
+    unregister_account(&account);
+    account.clear();
+
+    emit(EnigmatickEvent::LoggedOut {
+        actor: profile.id.to_string(),
+    });
+
+    invalidated.map(|_| ())
+}
+
+#[derive(Serialize)]
+struct UpdatePassword {
+    // proves knowledge of the current password
+    session: String,
+    m1: String,
+    #[serde(flatten)]
+    srp: SrpVerifier,
+    encrypted_client_private_key: String,
+    encrypted_olm_pickled_account: String,
+    kdf: KdfParams,
+    #[serde(flatten)]
+    recovery: Option<RecoveryWrap>,
+}
+
+// Stores the client key and olm account re-encrypted under the key derived
+// from updated_str with params, along with a new SRP verifier; the password
+// itself changes if updated_str differs from current_str
+async fn update_protected_keys(
+    profile: &Profile,
+    current_str: &str,
+    updated_str: &str,
+    params: &KdfParams,
+) -> EnigmatickResult<String> {
+    let url = format!("/api/user/{}/password", profile.username);
+
+    let proof = srp_prove(format!("{url}/start"), &profile.username, current_str).await?;
+
+    let salt = profile
+        .salt
+        .clone()
+        .ok_or(EnigmatickError::missing("salt"))?;
+    let encoded_derived_key =
+        encode_derived_key(&derive_key(updated_str.to_string(), salt, params)?);
+
+    // sealed in place, without copying the secrets out of state
+    let (encrypted_client_private_key, encrypted_olm_pickled_account, recovery) =
+        with_secrets(|secrets| {
+            let seal = |secret: &SecretString| {
+                encrypt_bytes(
+                    Some(encoded_derived_key.clone()),
+                    secret.expose().as_bytes(),
+                )
+                .map(|x| general_purpose::STANDARD.encode(x))
+            };
+
+            Ok((
+                seal(secrets.client_private_key_pem()?)?,
+                seal(secrets.olm_pickled_account()?)?,
+                rewrap_recovery(
+                    profile,
+                    secrets.derived_key()?.expose(),
+                    &encoded_derived_key,
+                )?,
+            ))
+        })?;
+
+    let data = serde_json::to_string(&UpdatePassword {
+        session: proof.session,
+        m1: proof.m1,
+        srp: srp_verifier(&profile.username, updated_str),
+        encrypted_client_private_key: encrypted_client_private_key.clone(),
+        encrypted_olm_pickled_account: encrypted_olm_pickled_account.clone(),
+        kdf: params.clone(),
+        recovery: recovery.clone(),
+    })?;
+
+    let resp = send_post(url, data, "application/json".to_string()).await?;
+
+    if resp != "200" {
+        return Err(EnigmatickError::parse(format!(
+            "unexpected response to password update: {resp}"
+        )));
+    }
+
+    // the profile has to match what the server now holds, or unlock (and
+    // the next login) would be working from the old ciphertexts
+    update_state(|state| {
+        if let Some(profile) = state.profile.as_mut() {
+            profile.client_private_key = Some(encrypted_client_private_key);
+            profile.olm_pickled_account = Some(encrypted_olm_pickled_account);
+            profile.kdf = Some(params.clone());
+            if let Some(recovery) = recovery {
+                recovery.apply(profile);
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(encoded_derived_key)
+}
+
+// Moves an account on outdated KDF parameters to the current ones: the client
+// key and olm account are stored under the new key, then the MLS credentials
+// and storage instruments are re-encrypted. The old key is kept (as a previous
+// key) for everything else that was sealed with it.
+async fn migrate_kdf(password_str: String) -> EnigmatickResult<()> {
+    authenticated(move |_: EnigmatickState, profile: Profile| async move {
+        // the MLS instruments have to be opened while the old key is current;
+        // accounts without MLS credentials have nothing to re-encrypt
+        let mls = retrieve_credentials().await.ok();
+
+        let params = KdfParams::of(&profile).upgrade();
+        let new_key =
+            update_protected_keys(&profile, &password_str, &password_str, &params).await?;
+
+        update_secrets(|secrets| {
+            secrets.replace_derived_key(new_key);
+            Ok(())
+        })?;
+
+        if let Some((credentials, provider, mutation_of)) = mls {
+            update_instruments(vec![
+                ApInstrument::from((credentials, ENCRYPT_FN)),
+                ApInstrument::from((provider.storage(), mutation_of, ENCRYPT_FN, HASH_FN)),
+            ])
+            .await?;
+        }
+
+        log("KDF parameters migrated");
+        Ok(())
+    })
+    .await
 }
 
-#[wasm_bindgen]
 pub async fn create_user(
     username: String,
     display_name: String,
     password_str: String,
-) -> Option<Profile> {
-    let key = get_key_pair();
+    key_algorithm: Option<KeyAlgorithm>,
+    recovery_phrase: Option<String>,
+) -> Result<Profile, EnigmatickError> {
+    let key = generate_client_key(key_algorithm.unwrap_or_default())?;
 
-    let client_public_key = key
-        .public_key
-        .to_public_key_pem(LineEnding::default())
-        .ok()?;
-    let client_private_key = key.private_key.to_pkcs8_pem(LineEnding::default()).ok()?;
-    let password = kdf::Password::from_slice(password_str.as_bytes()).ok()?;
+    let client_public_key = key.public_key;
+    let client_private_key = key.private_key;
+    let password =
+        kdf::Password::from_slice(password_str.as_bytes()).map_err(EnigmatickError::encryption)?;
 
     let salt = kdf::Salt::default();
+    let params = KdfParams::current();
 
-    let derived_key = kdf::derive_key(&password, &salt, 3, 1 << 4, 32).ok()?;
-    let password_hash = get_hash(password_str.clone().into_bytes())?;
+    let derived_key = kdf::derive_key(&password, &salt, params.iterations, params.memory_kib, 32)
+        .map_err(EnigmatickError::encryption)?;
+    let srp = srp_verifier(&username, &password_str);
     let salt = Some(general_purpose::STANDARD.encode(&salt));
     let encoded_derived_key = general_purpose::STANDARD.encode(derived_key.unprotected_as_bytes());
+    let recovery = recovery_phrase
+        .map(|phrase| recovery_setup(&username, &phrase, &encoded_derived_key))
+        .transpose()?;
 
-    let cpk_ciphertext = aead::seal(&derived_key, client_private_key.as_bytes()).ok()?;
+    let cpk_ciphertext = aead::seal(&derived_key, client_private_key.as_bytes())
+        .map_err(EnigmatickError::encryption)?;
     let encrypted_client_private_key = general_purpose::STANDARD.encode(cpk_ciphertext);
 
     let req = NewUser {
         username,
-        password: general_purpose::STANDARD.encode(password_hash),
+        srp,
         display_name,
         client_public_key: Some(client_public_key),
         client_private_key: Some(encrypted_client_private_key.clone()),
@@ -151,57 +477,58 @@ pub async fn create_user(
         olm_pickled_account_hash: None,
         olm_identity_key: None,
         salt,
+        kdf: Some(params),
+        recovery,
     };
 
-    async {
-        let response = post_object(
-            "/api/user/create".to_string(),
-            req,
-            "application/json",
-            None,
-        )
-        .await?;
-        let user: Option<Profile> = serde_json::from_str(&response).ok();
-
-        if let Some(user) = user.clone() {
-            update_state(|state| {
-                state.set_profile(user.clone());
-                state.set_derived_key(encoded_derived_key);
-                state.set_client_private_key_pem(client_private_key.clone().to_string());
-                Ok(())
-            })
-            .ok();
-        }
+    let response = post_object(
+        "/api/user/create".to_string(),
+        req,
+        "application/json",
+        None,
+    )
+    .await?;
+    let user: Profile = serde_json::from_str(&response)?;
 
-        user
-    }
-    .await
+    begin_account();
+
+    update_state(|state| {
+        state.set_profile(user.clone());
+        state.set_derived_key(encoded_derived_key);
+        state.set_client_private_key_pem(client_private_key.clone());
+        Ok(())
+    })?;
+
+    Ok(user)
 }
 
-pub async fn get_mls_keys() -> Option<ApCollection> {
-    authenticated(
-        move |state: EnigmatickState, _profile: Profile| async move {
-            let keys = format!("/user/{}/keys", state.get_profile()?.username);
+pub async fn get_mls_keys() -> EnigmatickResult<ApCollection> {
+    let keys = authenticated(
+        move |_state: EnigmatickState, profile: Profile| async move {
+            let keys = format!("/user/{}/keys", profile.username);
 
             send_get(None, keys, "application/activity+json".to_string()).await
         },
     )
-    .await
-    .and_then(|x| serde_json::from_str(&x).ok())
+    .await?;
+
+    Ok(serde_json::from_str(&keys)?)
 }
 
-#[wasm_bindgen]
-pub async fn upload_image(data: &[u8], length: u32) -> Option<String> {
+pub async fn upload_image(data: &[u8]) -> Result<String, EnigmatickError> {
     authenticated(move |_: EnigmatickState, profile: Profile| async move {
         let upload = format!("/api/user/{}/media", profile.username.clone());
 
-        upload_file(None, upload, data, length).await
+        let response = upload_file(None, upload, data).await?;
+        let attachment: ApAttachment = serde_json::from_str(&response)?;
+        //log(&format!("upload completed\n{attachment:#?}"));
+
+        Ok(serde_json::to_string(&attachment)?)
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn upload_avatar(data: &[u8], length: u32, extension: String) {
+pub async fn upload_avatar(data: &[u8], extension: String) -> Result<(), EnigmatickError> {
     authenticated(move |_: EnigmatickState, profile: Profile| async move {
         let upload = format!(
             "/api/user/{}/avatar?extension={}",
@@ -209,15 +536,14 @@ pub async fn upload_avatar(data: &[u8], length: u32, extension: String) {
             extension
         );
 
-        upload_file(None, upload, data, length).await;
+        upload_file(None, upload, data).await?;
 
-        None
+        Ok(())
     })
-    .await;
+    .await
 }
 
-#[wasm_bindgen]
-pub async fn upload_banner(data: &[u8], length: u32, extension: String) {
+pub async fn upload_banner(data: &[u8], extension: String) -> Result<(), EnigmatickError> {
     authenticated(move |_: EnigmatickState, profile: Profile| async move {
         let upload = format!(
             "/api/user/{}/banner?extension={}",
@@ -225,70 +551,55 @@ pub async fn upload_banner(data: &[u8], length: u32, extension: String) {
             extension
         );
 
-        upload_file(None, upload, data, length).await;
+        upload_file(None, upload, data).await?;
 
-        None
+        Ok(())
     })
-    .await;
+    .await
 }
 
-#[wasm_bindgen]
-pub async fn update_password(current_str: String, updated_str: String) -> bool {
-    authenticated(move |state: EnigmatickState, profile: Profile| async move {
-        let url = format!("/api/user/{}/password", profile.username);
+pub async fn update_password(
+    current_str: String,
+    updated_str: String,
+) -> Result<bool, EnigmatickError> {
+    authenticated(move |_: EnigmatickState, profile: Profile| async move {
+        // a password change is also an opportunity to move to the current
+        // parameters; keys derived from the old password cannot be kept
+        let params = KdfParams::current();
 
-        let current_hash = get_hash(current_str.clone().into_bytes())?;
-        let updated_hash = get_hash(updated_str.clone().into_bytes())?;
+        update_protected_keys(&profile, &current_str, &updated_str, &params).await?;
+        update_state_password(updated_str, params)?;
 
-        let current = general_purpose::STANDARD.encode(current_hash);
-        let updated = general_purpose::STANDARD.encode(updated_hash);
+        Ok(true)
+    })
+    .await
+}
 
-        #[derive(Serialize)]
-        struct UpdatePassword {
-            current: String,
-            updated: String,
-            encrypted_client_private_key: String,
-            encrypted_olm_pickled_account: String,
-        }
+// Replaces the client key pair (e.g., after a device is compromised)
+pub async fn rotate_client_key(
+    key_algorithm: Option<KeyAlgorithm>,
+    grace_period: Option<u32>,
+) -> Result<Profile, EnigmatickError> {
+    authenticated(move |state: EnigmatickState, profile: Profile| async move {
+        let algorithm = match key_algorithm {
+            Some(algorithm) => algorithm,
+            None => with_secrets(|secrets| {
+                Ok(client_signing_key(secrets.client_private_key_pem()?.expose())?.algorithm())
+            })?,
+        };
 
-        let encoded_derived_key = encode_derived_key(
-            &derive_key(updated_str.clone(), state.profile.clone()?.salt?).ok()?,
-        );
-        let encrypted_client_private_key = encrypt(
-            Some(encoded_derived_key.clone()),
-            state.client_private_key_pem.clone()?,
+        publish_client_key(
+            &state,
+            &profile,
+            generate_client_key(algorithm)?,
+            grace_period.unwrap_or(DEFAULT_KEY_GRACE_PERIOD),
         )
-        .ok()?;
-        let encrypted_olm_pickled_account = encrypt(
-            Some(encoded_derived_key.clone()),
-            state.get_olm_pickled_account()?,
-        )
-        .ok()?;
-
-        let data = serde_json::to_string(&UpdatePassword {
-            current,
-            updated,
-            encrypted_client_private_key,
-            encrypted_olm_pickled_account,
-        })
-        .unwrap();
-
-        send_post(url, data, "application/json".to_string())
-            .await
-            .and_then(|resp| {
-                if resp == "200" {
-                    update_state_password(updated_str.clone()).ok()
-                } else {
-                    None
-                }
-            })
+        .await
     })
     .await
-    .is_some()
 }
 
-#[wasm_bindgen]
-pub async fn update_summary(summary: String, markdown: String) -> Option<String> {
+pub async fn update_summary(summary: String, markdown: String) -> Result<String, EnigmatickError> {
     authenticated(move |_: EnigmatickState, profile: Profile| async move {
         #[derive(Serialize, Deserialize)]
         struct SummaryUpdate {
@@ -303,49 +614,59 @@ pub async fn update_summary(summary: String, markdown: String) -> Option<String>
 
         let url = format!("/api/user/{}/update/summary", profile.username);
 
-        let data = serde_json::to_string(&data).unwrap();
+        let data = serde_json::to_string(&data)?;
         send_post(url, data, "application/json".to_string()).await
     })
     .await
 }
 
-pub async fn update_instruments(packages: Vec<ApInstrument>) -> Option<String> {
+pub async fn update_instruments(packages: Vec<ApInstrument>) -> EnigmatickResult<String> {
     authenticated(move |_: EnigmatickState, profile: Profile| async move {
+        let mutates_storage = packages.iter().any(|x| x.is_mls_storage());
         let collection: ApCollection = packages.into();
         let username = profile.username;
         let url = format!("/user/{username}");
 
-        let data = serde_json::to_string(&collection).unwrap();
+        let data = serde_json::to_string(&collection)?;
         //log(&format!("{data:#?}"));
-        send_post(url, data, "application/activity+json".to_string()).await
+        let response = send_post(url, data, "application/activity+json".to_string()).await?;
+
+        if mutates_storage {
+            emit(EnigmatickEvent::MlsStorageMutated {
+                actor: profile.id.to_string(),
+            });
+        }
+
+        Ok(response)
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn get_ap_id() -> Option<String> {
+pub async fn get_ap_id() -> Result<String, EnigmatickError> {
     authenticated(move |state: EnigmatickState, profile: Profile| async move {
         let username = profile.username;
-        let server = state.get_server_url();
+        let server = state
+            .get_server_url()
+            .ok_or(EnigmatickError::missing("server_url"))?;
 
-        server.map(|server| format!("{server}/user/{username}"))
+        Ok(format!("{server}/user/{username}"))
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn get_webfinger() -> Option<String> {
+pub async fn get_webfinger() -> Result<String, EnigmatickError> {
     authenticated(move |state: EnigmatickState, profile: Profile| async move {
         let username = profile.username;
-        let server = state.get_server_name();
+        let server = state
+            .get_server_name()
+            .ok_or(EnigmatickError::missing("server_name"))?;
 
-        server.map(|server| format!("@{username}@{server}"))
+        Ok(format!("@{username}@{server}"))
     })
     .await
 }
 
-#[wasm_bindgen]
-pub async fn get_followers(username: String, page: Option<u32>) -> Option<String> {
+pub async fn get_followers(username: String, page: Option<u32>) -> Result<String, EnigmatickError> {
     authenticated(move |_: EnigmatickState, _: Profile| async move {
         let url = {
             if let Some(page) = page {
@@ -360,8 +681,7 @@ pub async fn get_followers(username: String, page: Option<u32>) -> Option<String
     .await
 }
 
-#[wasm_bindgen]
-pub async fn get_following(username: String, page: Option<u32>) -> Option<String> {
+pub async fn get_following(username: String, page: Option<u32>) -> Result<String, EnigmatickError> {
     authenticated(move |_: EnigmatickState, _: Profile| async move {
         let url = {
             if let Some(page) = page {
@@ -376,15 +696,13 @@ pub async fn get_following(username: String, page: Option<u32>) -> Option<String
     .await
 }
 
-#[wasm_bindgen]
-pub async fn get_profile_by_username(username: String) -> Option<String> {
+pub async fn get_profile_by_username(username: String) -> Result<String, EnigmatickError> {
     let actor: ApActor = get_object(
         format!("/api/user/{username}"),
         None,
         "application/activity+json",
     )
-    .await
-    .ok()?;
+    .await?;
 
-    Some(serde_json::to_string(&actor).unwrap())
+    Ok(serde_json::to_string(&actor)?)
 }
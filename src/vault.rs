@@ -1,14 +1,12 @@
 use base64::{engine::general_purpose, engine::Engine as _};
-use jdt_activity_pub::ApCollection;
+use jdt_activity_pub::{ApCollection, ApInstrument};
 use serde::{Deserialize, Serialize};
-use wasm_bindgen::prelude::wasm_bindgen;
 
 use crate::{
-    authenticated, encrypt, error, get_hash, log, resolve_processed_item, send_get, send_post,
-    EnigmatickState, Profile,
+    authenticated, encrypt, error, get_hash, instruments, log, resolve_processed_item, send_get,
+    send_post, EnigmatickError, EnigmatickResult, EnigmatickState, Profile,
 };
 
-#[wasm_bindgen]
 pub async fn store_to_vault(
     data: String,
     remote_actor: String,
@@ -16,7 +14,7 @@ pub async fn store_to_vault(
     session_uuid: String,
     session: String,
     mutation_of: String,
-) -> Option<String> {
+) -> Result<String, EnigmatickError> {
     authenticated(move |_: EnigmatickState, profile: Profile| async move {
         #[derive(Serialize, Debug, Clone)]
         pub struct SessionUpdate {
@@ -33,43 +31,32 @@ pub async fn store_to_vault(
             pub session: SessionUpdate,
         }
 
-        if let (Ok(encrypted_session), Some(session_hash)) = (
-            encrypt(None, session.clone()),
-            get_hash(session.into_bytes()),
-        ) {
-            let session = SessionUpdate {
-                session_uuid,
-                encrypted_session,
-                session_hash,
-                mutation_of,
-            };
-
-            let url = format!("/api/user/{}/vault", profile.username.clone());
-
-            if let Ok(data) = encrypt(None, data) {
-                if send_post(
-                    url,
-                    serde_json::to_string(&VaultStorageRequest {
-                        data,
-                        remote_actor,
-                        session,
-                    })
-                    .unwrap(),
-                    "application/json".to_string(),
-                )
-                .await
-                .is_some()
-                {
-                    resolve_processed_item(resolves).await
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+        let encrypted_session = encrypt(None, session.clone())?;
+        let session_hash = get_hash(session.into_bytes())?;
+
+        let session = SessionUpdate {
+            session_uuid,
+            encrypted_session,
+            session_hash,
+            mutation_of,
+        };
+
+        let url = format!("/api/user/{}/vault", profile.username.clone());
+
+        let data = encrypt(None, data)?;
+
+        send_post(
+            url,
+            serde_json::to_string(&VaultStorageRequest {
+                data,
+                remote_actor,
+                session,
+            })?,
+            "application/json".to_string(),
+        )
+        .await?;
+
+        resolve_processed_item(resolves).await
     })
     .await
 }
@@ -83,8 +70,7 @@ pub struct VaultRetrievalItem {
     pub data: String,
 }
 
-#[wasm_bindgen]
-pub async fn get_vault(offset: i32, limit: i32, actor: String) -> Option<String> {
+pub async fn get_vault(offset: i32, limit: i32, actor: String) -> Result<String, EnigmatickError> {
     //log("IN get vault");
 
     authenticated(move |_: EnigmatickState, profile: Profile| async move {
@@ -93,22 +79,56 @@ pub async fn get_vault(offset: i32, limit: i32, actor: String) -> Option<String>
         let actor = general_purpose::STANDARD.encode(actor);
         let url = format!("/api/user/{username}/vault?offset={offset}&limit={limit}&actor={actor}");
 
-        if let Some(data) = send_get(None, url, "application/json".to_string()).await {
-            error(&format!("VAULT RESPONSE\n{:#?}", data));
-            // if let Ok(items) = serde_json::from_str::<Vec<VaultRetrievalItem>>(&data) {
-            //     Option::from(serde_json::to_string(&items).unwrap())
-            // } else {
-            //     Option::None
-            // }
-
-            if let Ok(object) = serde_json::from_str::<ApCollection>(&data) {
-                Option::from(serde_json::to_string(&object).unwrap())
-            } else {
-                Option::None
-            }
-        } else {
-            Option::None
-        }
+        let data = send_get(None, url, "application/json".to_string()).await?;
+        error(&format!("VAULT RESPONSE\n{:#?}", data));
+        // if let Ok(items) = serde_json::from_str::<Vec<VaultRetrievalItem>>(&data) {
+        //     Option::from(serde_json::to_string(&items).unwrap())
+        // } else {
+        //     Option::None
+        // }
+
+        let object: ApCollection = serde_json::from_str(&data)?;
+        Ok(serde_json::to_string(&object)?)
     })
     .await
 }
+
+// Every vault item of the account, with its content still sealed
+pub(crate) async fn get_vault_items(profile: &Profile) -> EnigmatickResult<Vec<ApInstrument>> {
+    const PAGE: usize = 100;
+
+    let mut items = vec![];
+    loop {
+        let url = format!(
+            "/api/user/{}/vault?offset={}&limit={PAGE}",
+            profile.username,
+            items.len()
+        );
+        let response = send_get(None, url, "application/json".to_string()).await?;
+        let page = instruments(serde_json::from_str(&response)?);
+        let count = page.len();
+
+        items.extend(page.into_iter().filter(|x| x.is_vault_item()));
+
+        if count < PAGE {
+            return Ok(items);
+        }
+    }
+}
+
+// Stores vault items whose content has been sealed (again) under the current
+// key, e.g., when restoring a backup; items that already exist are replaced
+pub(crate) async fn store_vault_items(
+    profile: &Profile,
+    items: Vec<ApInstrument>,
+) -> EnigmatickResult<String> {
+    let url = format!("/api/user/{}/vault", profile.username);
+    let collection: ApCollection = items.into();
+
+    send_post(
+        url,
+        serde_json::to_string(&collection)?,
+        "application/activity+json".to_string(),
+    )
+    .await
+}
@@ -0,0 +1,715 @@
+use base64::{engine::general_purpose, engine::Engine as _};
+use jdt_activity_pub::{ApActor, ApPublicKey};
+use lazy_static::lazy_static;
+use regex::Regex;
+use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::signature::Verifier;
+use rsa::RsaPublicKey;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::{
+    client_key_id, get_actor_with_options, get_state, send_get, server_now, EnigmatickError,
+    EnigmatickResult, RequestOptions, SignatureFormat,
+};
+
+lazy_static! {
+    static ref PARAMETER_RE: Regex =
+        Regex::new(r#"(\w+)="([^"]*)""#).expect("invalid signature parameter regex");
+    static ref NUMERIC_PARAMETER_RE: Regex =
+        Regex::new(r#";(\w+)=(\d+)"#).expect("invalid signature parameter regex");
+    static ref COMPONENT_RE: Regex = Regex::new(r#""([^"]+)""#).expect("invalid component regex");
+}
+
+// how far (in seconds) a signature's date may be ahead of the server clock,
+// or behind it past an expiry
+const CLOCK_SKEW: i64 = 60 * 60;
+
+// the age (in seconds) after which a signature with no expiry is refused
+const MAX_SIGNATURE_AGE: i64 = 12 * 60 * 60;
+
+// A request (or response) as received, to be checked against its signature
+#[derive(Clone, Debug, Default)]
+pub struct VerifyParams {
+    pub method: String,
+    // path and query as sent, e.g. /user/jdt/inbox
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct VerifiedSignature {
+    pub key_id: String,
+    pub format: SignatureFormat,
+    pub algorithm: Option<String>,
+    // the signed headers/components, in signature order
+    pub covered: Vec<String>,
+}
+
+struct ParsedSignature {
+    format: SignatureFormat,
+    key_id: String,
+    algorithm: Option<String>,
+    covered: Vec<String>,
+    signing_string: String,
+    signature: Vec<u8>,
+    // the signed date header and created/expires parameters, if any
+    date: Option<String>,
+    created: Option<i64>,
+    expires: Option<i64>,
+}
+
+fn invalid(message: impl Into<String>) -> EnigmatickError {
+    EnigmatickError::Verification(message.into())
+}
+
+impl VerifyParams {
+    fn header(&self, name: &str) -> Option<String> {
+        let values = self
+            .headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+            .collect::<Vec<&str>>();
+
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    fn required_header(&self, name: &str) -> EnigmatickResult<String> {
+        self.header(name)
+            .ok_or_else(|| invalid(format!("signed header {name} is missing")))
+    }
+
+    fn format(&self) -> SignatureFormat {
+        if self.header("Signature-Input").is_some() {
+            SignatureFormat::Rfc9421
+        } else {
+            SignatureFormat::Cavage
+        }
+    }
+}
+
+// Splits a structured field dictionary on top-level commas
+fn split_members(value: &str) -> Vec<&str> {
+    let mut members = vec![];
+    let (mut depth, mut quoted, mut start) = (0, false, 0);
+
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                members.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    members.push(value[start..].trim());
+
+    members
+}
+
+fn dictionary(value: &str) -> HashMap<String, String> {
+    split_members(value)
+        .into_iter()
+        .filter_map(|member| {
+            let (key, value) = member.split_once('=')?;
+            Some((key.trim().to_lowercase(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+fn parse_cavage(params: &VerifyParams) -> EnigmatickResult<ParsedSignature> {
+    let header = params
+        .header("Signature")
+        .ok_or_else(|| invalid("no Signature header"))?;
+
+    let fields: HashMap<String, String> = PARAMETER_RE
+        .captures_iter(&header)
+        .map(|x| (x[1].to_lowercase(), x[2].to_string()))
+        .collect();
+    let numeric: HashMap<String, String> = NUMERIC_PARAMETER_RE
+        .captures_iter(&header.replace(',', ";"))
+        .map(|x| (x[1].to_lowercase(), x[2].to_string()))
+        .collect();
+
+    let key_id = fields
+        .get("keyid")
+        .cloned()
+        .ok_or_else(|| invalid("Signature has no keyId"))?;
+    let signature = general_purpose::STANDARD.decode(
+        fields
+            .get("signature")
+            .ok_or_else(|| invalid("Signature has no signature"))?,
+    )?;
+
+    let covered = fields
+        .get("headers")
+        .map(|x| x.split_whitespace().map(str::to_lowercase).collect())
+        .unwrap_or_else(|| vec!["date".to_string()]);
+
+    let lines = covered
+        .iter()
+        .map(|name| {
+            let value = match name.as_str() {
+                "(request-target)" => format!("{} {}", params.method.to_lowercase(), params.path),
+                "(created)" | "(expires)" => numeric
+                    .get(name.trim_matches(|c| c == '(' || c == ')'))
+                    .cloned()
+                    .ok_or_else(|| invalid(format!("Signature has no {name} parameter")))?,
+                // browsers cannot set Date, so Enigmatick clients send their
+                // signing date as Enigmatick-Date instead
+                "date" => params
+                    .header("Date")
+                    .or_else(|| params.header("Enigmatick-Date"))
+                    .ok_or_else(|| invalid("signed header date is missing"))?,
+                _ => params.required_header(name)?,
+            };
+
+            Ok(format!("{name}: {value}"))
+        })
+        .collect::<EnigmatickResult<Vec<String>>>()?;
+
+    let date = covered
+        .iter()
+        .any(|x| x == "date")
+        .then(|| {
+            params
+                .header("Date")
+                .or_else(|| params.header("Enigmatick-Date"))
+        })
+        .flatten();
+
+    // created and expires are only signed when (created) and (expires) are
+    // covered; anything else could have been appended to the header
+    let signed_parameter = |name: &str| {
+        covered
+            .iter()
+            .any(|x| x == &format!("({name})"))
+            .then(|| numeric.get(name).and_then(|x| x.parse().ok()))
+            .flatten()
+    };
+
+    Ok(ParsedSignature {
+        format: SignatureFormat::Cavage,
+        key_id,
+        algorithm: fields.get("algorithm").cloned(),
+        signing_string: lines.join("\n"),
+        signature,
+        date,
+        created: signed_parameter("created"),
+        expires: signed_parameter("expires"),
+        covered,
+    })
+}
+
+fn parse_rfc9421(params: &VerifyParams) -> EnigmatickResult<ParsedSignature> {
+    let inputs = dictionary(
+        &params
+            .header("Signature-Input")
+            .ok_or_else(|| invalid("no Signature-Input header"))?,
+    );
+    let signatures = dictionary(
+        &params
+            .header("Signature")
+            .ok_or_else(|| invalid("no Signature header"))?,
+    );
+
+    // when several signatures are present, check the first one that has both
+    // an input and a value
+    let (input, signature) = split_members(&params.header("Signature-Input").unwrap_or_default())
+        .into_iter()
+        .filter_map(|member| member.split_once('='))
+        .find_map(|(label, _)| {
+            let label = label.trim().to_lowercase();
+            Some((inputs.get(&label)?.clone(), signatures.get(&label)?.clone()))
+        })
+        .ok_or_else(|| invalid("Signature-Input and Signature have no label in common"))?;
+
+    let signature = general_purpose::STANDARD.decode(signature.trim_matches(':'))?;
+
+    let fields: HashMap<String, String> = PARAMETER_RE
+        .captures_iter(&input)
+        .map(|x| (x[1].to_lowercase(), x[2].to_string()))
+        .collect();
+    let numeric: HashMap<String, String> = NUMERIC_PARAMETER_RE
+        .captures_iter(&input)
+        .map(|x| (x[1].to_lowercase(), x[2].to_string()))
+        .collect();
+    let key_id = fields
+        .get("keyid")
+        .cloned()
+        .ok_or_else(|| invalid("Signature-Input has no keyid"))?;
+
+    let inner_list = input
+        .split_once(')')
+        .map(|(list, _)| list.trim_start_matches('('))
+        .ok_or_else(|| invalid("Signature-Input has no component list"))?;
+    let covered: Vec<String> = COMPONENT_RE
+        .captures_iter(inner_list)
+        .map(|x| x[1].to_lowercase())
+        .collect();
+
+    let (path, query) = params
+        .path
+        .split_once('?')
+        .unwrap_or((params.path.as_str(), ""));
+
+    let mut lines = covered
+        .iter()
+        .map(|name| {
+            let value = match name.as_str() {
+                "@method" => params.method.to_uppercase(),
+                "@path" => path.to_string(),
+                "@query" => format!("?{query}"),
+                "@request-target" => params.path.clone(),
+                "@authority" => params.required_header("Host")?.to_lowercase(),
+                "@scheme" => "https".to_string(),
+                "@target-uri" => format!(
+                    "https://{}{}",
+                    params.required_header("Host")?.to_lowercase(),
+                    params.path
+                ),
+                x if x.starts_with('@') => {
+                    return Err(invalid(format!("unsupported derived component {x}")))
+                }
+                _ => params.required_header(name)?,
+            };
+
+            Ok(format!("\"{name}\": {value}"))
+        })
+        .collect::<EnigmatickResult<Vec<String>>>()?;
+    lines.push(format!("\"@signature-params\": {input}"));
+
+    let date = covered
+        .iter()
+        .any(|x| x == "date")
+        .then(|| params.header("Date"))
+        .flatten();
+
+    Ok(ParsedSignature {
+        format: SignatureFormat::Rfc9421,
+        key_id,
+        algorithm: fields.get("alg").cloned(),
+        signing_string: lines.join("\n"),
+        signature,
+        date,
+        created: numeric.get("created").and_then(|x| x.parse().ok()),
+        expires: numeric.get("expires").and_then(|x| x.parse().ok()),
+        covered,
+    })
+}
+
+fn parse(params: &VerifyParams) -> EnigmatickResult<ParsedSignature> {
+    match params.format() {
+        SignatureFormat::Cavage => parse_cavage(params),
+        SignatureFormat::Rfc9421 => parse_rfc9421(params),
+    }
+}
+
+// Refuses undated signatures and those dated too far in the future, older
+// than MAX_SIGNATURE_AGE, or past their expiry; now is in seconds
+fn check_freshness(parsed: &ParsedSignature, now: i64) -> EnigmatickResult<()> {
+    if parsed.date.is_none() && parsed.created.is_none() {
+        return Err(invalid("signature covers neither a date nor created"));
+    }
+
+    let date = parsed
+        .date
+        .as_deref()
+        .map(|x| {
+            httpdate::parse_http_date(x)
+                .ok()
+                .and_then(|x| x.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|x| x.as_secs() as i64)
+                .ok_or_else(|| invalid(format!("unable to parse signed date {x}")))
+        })
+        .transpose()?;
+
+    for signed in [date, parsed.created].into_iter().flatten() {
+        if signed > now + CLOCK_SKEW {
+            return Err(invalid("signature is dated in the future"));
+        }
+
+        if parsed.expires.is_none() && signed < now - MAX_SIGNATURE_AGE {
+            return Err(invalid("signature is too old"));
+        }
+    }
+
+    if parsed.expires.is_some_and(|x| x + CLOCK_SKEW < now) {
+        return Err(invalid("signature has expired"));
+    }
+
+    Ok(())
+}
+
+// Checks Digest and Content-Digest against the body; returns the names of
+// the headers that matched
+fn verify_digests(params: &VerifyParams) -> EnigmatickResult<Vec<&'static str>> {
+    let body = match &params.body {
+        Some(body) if !body.is_empty() => body,
+        _ => return Ok(vec![]),
+    };
+
+    let expected = general_purpose::STANDARD.encode(Sha256::digest(body));
+    let mut verified = vec![];
+
+    if let Some(digest) = params.header("Digest") {
+        let value = digest
+            .split(',')
+            .filter_map(|x| x.trim().split_once('='))
+            .find(|(algorithm, _)| algorithm.eq_ignore_ascii_case("sha-256"))
+            .map(|(_, value)| value.to_string())
+            .ok_or_else(|| invalid("Digest has no sha-256 value"))?;
+
+        if value != expected {
+            return Err(invalid("Digest does not match the body"));
+        }
+        verified.push("digest");
+    }
+
+    if let Some(content_digest) = params.header("Content-Digest") {
+        let value = dictionary(&content_digest)
+            .get("sha-256")
+            .map(|x| x.trim_matches(':').to_string())
+            .ok_or_else(|| invalid("Content-Digest has no sha-256 value"))?;
+
+        if value != expected {
+            return Err(invalid("Content-Digest does not match the body"));
+        }
+        verified.push("content-digest");
+    }
+
+    if verified.is_empty() {
+        return Err(invalid("the body is not covered by a digest"));
+    }
+
+    Ok(verified)
+}
+
+fn verify_bytes(public_key_pem: &str, message: &str, signature: &[u8]) -> EnigmatickResult<()> {
+    // the algorithm is taken from the key rather than from the signature's
+    // (optional, and often "hs2019") algorithm parameter
+    let verified = if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_pem(public_key_pem)
+    {
+        let signature =
+            ed25519_dalek::Signature::from_slice(signature).map_err(|e| invalid(e.to_string()))?;
+        key.verify(message.as_bytes(), &signature)
+    } else {
+        let key = RsaPublicKey::from_public_key_pem(public_key_pem)
+            .or_else(|_| RsaPublicKey::from_pkcs1_pem(public_key_pem))
+            .map_err(|e| invalid(format!("unable to parse public key: {e}")))?;
+        let signature = Signature::try_from(signature).map_err(|e| invalid(e.to_string()))?;
+        VerifyingKey::<Sha256>::new(key).verify(message.as_bytes(), &signature)
+    };
+
+    verified.map_err(|_| invalid("signature does not match"))
+}
+
+// The keyId (or RFC 9421 keyid) the request claims to be signed with
+pub fn signature_key_id(params: &VerifyParams) -> EnigmatickResult<String> {
+    Ok(parse(params)?.key_id)
+}
+
+// The counterpart to crypto::sign: checks a draft-cavage or RFC 9421 signature
+// (chosen by the presence of Signature-Input), its dates and any body digests
+// against the given public key
+pub fn verify(params: &VerifyParams, public_key_pem: &str) -> EnigmatickResult<VerifiedSignature> {
+    verify_at(params, public_key_pem, (server_now() / 1_000.0) as i64)
+}
+
+fn verify_at(
+    params: &VerifyParams,
+    public_key_pem: &str,
+    now: i64,
+) -> EnigmatickResult<VerifiedSignature> {
+    let parsed = parse(params)?;
+    check_freshness(&parsed, now)?;
+
+    let digests = verify_digests(params)?;
+
+    if !digests.is_empty()
+        && !digests
+            .iter()
+            .any(|x| parsed.covered.iter().any(|y| y == x))
+    {
+        return Err(invalid("the body digest is not covered by the signature"));
+    }
+
+    verify_bytes(public_key_pem, &parsed.signing_string, &parsed.signature)?;
+
+    Ok(VerifiedSignature {
+        key_id: parsed.key_id,
+        format: parsed.format,
+        algorithm: parsed.algorithm,
+        covered: parsed.covered,
+    })
+}
+
+// Verifies a signature with the key published by the signing actor, fetched
+// via get_actor. If actor is given, the key must also belong to that actor;
+// this is how a server-relayed object is tied back to its claimed author.
+pub async fn verify_with_actor(
+    params: &VerifyParams,
+    actor: Option<String>,
+) -> EnigmatickResult<VerifiedSignature> {
+    let key_id = signature_key_id(params)?;
+    let actor_id = actor.unwrap_or_else(|| {
+        key_id
+            .split_once('#')
+            .map(|(x, _)| x.to_string())
+            .unwrap_or(key_id.clone())
+    });
+
+    let actor: ApActor = serde_json::from_str(
+        &get_actor_with_options(actor_id.clone(), RequestOptions::new()).await?,
+    )?;
+
+    verify(params, &actor_key(&actor, &actor_id, &key_id).await?)
+}
+
+// The PEM of key_id, which must be either the actor's published key or one of
+// its client keys (#client-key, or #client-key-<n> after a rotation). Client
+// keys are not part of the actor, so they are looked up through the server.
+async fn actor_key(actor: &ApActor, actor_id: &str, key_id: &str) -> EnigmatickResult<String> {
+    if actor.public_key.id == key_id {
+        return Ok(actor.public_key.public_key_pem.clone());
+    }
+
+    let not_published = || invalid(format!("{key_id} is not a published key of {actor_id}"));
+
+    match key_id.split_once('#') {
+        Some((owner, fragment)) if owner == actor_id && fragment.starts_with("client-key") => {}
+        _ => return Err(not_published()),
+    }
+
+    // our own key needs no lookup
+    let state = get_state();
+    if let Some(profile) = state.profile.as_ref() {
+        if client_key_id(&state, profile).is_ok_and(|x| x == key_id) {
+            if let Some(pem) = profile.client_public_key.clone() {
+                return Ok(pem);
+            }
+        }
+    }
+
+    let url = format!(
+        "/api/remote/client-key?keyId={}",
+        urlencoding::encode(key_id)
+    );
+    let key: ApPublicKey =
+        serde_json::from_str(&send_get(None, url, "application/activity+json".to_string()).await?)?;
+
+    if key.id != key_id || key.owner != actor_id {
+        return Err(not_published());
+    }
+
+    Ok(key.public_key_pem)
+}
+
+// headers is a JSON object of header names to values; returns the verified
+// signature as JSON
+pub async fn verify_signature(
+    method: String,
+    path: String,
+    headers: String,
+    body: Option<String>,
+    actor: Option<String>,
+) -> Result<String, EnigmatickError> {
+    let headers: HashMap<String, String> = serde_json::from_str(&headers)?;
+
+    let params = VerifyParams {
+        method,
+        path,
+        headers: headers.into_iter().collect(),
+        body: body.map(String::into_bytes),
+    };
+
+    Ok(serde_json::to_string(
+        &verify_with_actor(&params, actor).await?,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generate_client_key, set_signature_format, sign, update_state, Client, KeyAlgorithm,
+        Method, Profile, SignParams,
+    };
+
+    const KEY_ID: &str = "https://example.com/user/alice#client-key";
+    const PATH: &str = "/user/alice/outbox";
+    const BODY: &str = r#"{"type":"Note","content":"hello"}"#;
+
+    // Signs a POST to host as a client would and returns the request as a
+    // server would receive it, with the public key to check it against
+    fn signed_request(
+        host: &str,
+        algorithm: KeyAlgorithm,
+        format: SignatureFormat,
+    ) -> (VerifyParams, String) {
+        let key = generate_client_key(algorithm).unwrap();
+
+        let client = Client::new();
+        let signed = client.enter(|| {
+            set_signature_format(host.to_string(), format);
+            update_state(|state| {
+                state.set_profile(Profile {
+                    username: "alice".to_string(),
+                    client_key_id: Some(KEY_ID.to_string()),
+                    ..Profile::default()
+                });
+                state.set_client_private_key_pem(key.private_key.clone());
+                Ok(())
+            })
+            .unwrap();
+
+            sign(SignParams {
+                host: host.to_string(),
+                request_target: PATH.to_string(),
+                body: Some(BODY.to_string()),
+                data: None,
+                method: Method::Post,
+            })
+            .unwrap()
+        });
+
+        let mut headers = vec![
+            ("Host".to_string(), host.to_string()),
+            ("Enigmatick-Date".to_string(), signed.date),
+            ("Signature".to_string(), signed.signature),
+        ];
+        headers.extend(signed.digest.map(|x| ("Digest".to_string(), x)));
+        headers.extend(
+            signed
+                .content_digest
+                .map(|x| ("Content-Digest".to_string(), x)),
+        );
+        headers.extend(
+            signed
+                .signature_input
+                .map(|x| ("Signature-Input".to_string(), x)),
+        );
+
+        let params = VerifyParams {
+            method: "POST".to_string(),
+            path: PATH.to_string(),
+            headers,
+            body: Some(BODY.as_bytes().to_vec()),
+        };
+
+        (params, key.public_key)
+    }
+
+    #[test]
+    fn verifies_cavage_signatures() {
+        for algorithm in [KeyAlgorithm::Ed25519, KeyAlgorithm::Rsa] {
+            let (params, public_key) =
+                signed_request("cavage.example", algorithm, SignatureFormat::Cavage);
+            let verified = verify(&params, &public_key).unwrap();
+
+            assert_eq!(verified.format, SignatureFormat::Cavage);
+            assert_eq!(verified.key_id, KEY_ID);
+            assert!(verified.covered.contains(&"digest".to_string()));
+        }
+    }
+
+    #[test]
+    fn verifies_rfc9421_signatures() {
+        for algorithm in [KeyAlgorithm::Ed25519, KeyAlgorithm::Rsa] {
+            let (params, public_key) =
+                signed_request("rfc9421.example", algorithm, SignatureFormat::Rfc9421);
+            let verified = verify(&params, &public_key).unwrap();
+
+            assert_eq!(verified.format, SignatureFormat::Rfc9421);
+            assert_eq!(verified.key_id, KEY_ID);
+            assert!(verified.covered.contains(&"content-digest".to_string()));
+        }
+    }
+
+    #[test]
+    fn refuses_altered_requests() {
+        for (host, format) in [
+            ("cavage.example", SignatureFormat::Cavage),
+            ("rfc9421.example", SignatureFormat::Rfc9421),
+        ] {
+            let (params, public_key) = signed_request(host, KeyAlgorithm::Ed25519, format);
+
+            let altered = VerifyParams {
+                body: Some(br#"{"type":"Note","content":"goodbye"}"#.to_vec()),
+                ..params.clone()
+            };
+            assert!(verify(&altered, &public_key).is_err());
+
+            let redirected = VerifyParams {
+                path: "/user/bob/outbox".to_string(),
+                ..params.clone()
+            };
+            assert!(verify(&redirected, &public_key).is_err());
+
+            let other_key = generate_client_key(KeyAlgorithm::Ed25519).unwrap();
+            assert!(verify(&params, &other_key.public_key).is_err());
+        }
+    }
+
+    #[test]
+    fn refuses_stale_signatures() {
+        for (host, format) in [
+            ("cavage.example", SignatureFormat::Cavage),
+            ("rfc9421.example", SignatureFormat::Rfc9421),
+        ] {
+            let (params, public_key) = signed_request(host, KeyAlgorithm::Ed25519, format);
+            let now = (server_now() / 1_000.0) as i64;
+
+            assert!(verify_at(&params, &public_key, now).is_ok());
+            assert!(verify_at(&params, &public_key, now + MAX_SIGNATURE_AGE + 60).is_err());
+            assert!(verify_at(&params, &public_key, now - CLOCK_SKEW - 60).is_err());
+        }
+    }
+
+    #[test]
+    fn refuses_unsigned_expires() {
+        let (mut params, public_key) = signed_request(
+            "cavage.example",
+            KeyAlgorithm::Ed25519,
+            SignatureFormat::Cavage,
+        );
+        let now = (server_now() / 1_000.0) as i64;
+
+        for (name, value) in params.headers.iter_mut() {
+            if name == "Signature" {
+                value.push_str(",expires=99999999999");
+            }
+        }
+
+        assert!(verify_at(&params, &public_key, now + MAX_SIGNATURE_AGE + 60).is_err());
+    }
+
+    #[test]
+    fn refuses_undated_signatures() {
+        let parsed = ParsedSignature {
+            format: SignatureFormat::Cavage,
+            key_id: KEY_ID.to_string(),
+            algorithm: None,
+            covered: vec!["(request-target)".to_string()],
+            signing_string: String::new(),
+            signature: vec![],
+            date: None,
+            created: None,
+            expires: None,
+        };
+
+        assert!(check_freshness(&parsed, 0).is_err());
+    }
+}